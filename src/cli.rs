@@ -1,19 +1,48 @@
+pub mod backup;
 pub mod clear;
+pub mod config;
+pub mod doctor;
+pub mod history;
 pub mod plan;
+pub mod preset;
+pub mod profile;
+pub mod recur;
+pub mod storage;
+pub mod template;
 pub mod timer;
+pub mod trash;
 pub mod view;
 
 use crate::domain::{Data, Executable};
+use crate::storage::Store;
+use crate::utils::parsers;
+use backup::BackupCommands;
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
+use config::ConfigCommands;
 use plan::PlanCommands;
+use profile::ProfileCommands;
 use std::error::Error;
+use std::path::PathBuf;
+use storage::StorageCommands;
+use template::TemplateCommands;
 use timer::TimerCommands;
+use trash::TrashCommands;
 
 #[derive(Parser)]
 #[command(name = "zyr")]
 #[command(version = "0.1.0")]
 #[command(about = "Productivity timer and manager")]
 pub struct Cli {
+    /// Use the data file at this path instead of the one of the active profile.
+    /// Can also be set with the ZYR_DATA environment variable.
+    #[arg(long, global = true, value_name = "PATH", conflicts_with = "profile")]
+    pub data: Option<PathBuf>,
+
+    /// Use the data file of this profile instead of the active one
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -30,18 +59,107 @@ pub enum Commands {
         #[command(subcommand)]
         command: PlanCommands,
     },
-    /// Reset all data stored on this device
-    Clear,
+    /// Manage separate profiles, each with its own data file
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// List, restore, or permanently delete deleted time blocks
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommands,
+    },
+    /// Create, list, and restore backups of the data
+    Backup {
+        #[command(subcommand)]
+        command: BackupCommands,
+    },
+    /// Change how the data is stored on disk
+    Storage {
+        #[command(subcommand)]
+        command: StorageCommands,
+    },
+    /// Show or change settings
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Save days as templates and plan other days with them
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommands,
+    },
+    /// Reset all data stored on this device, or only the time blocks in a scope.
+    /// E.g., zyr clear --before 2024-01-01 --category break
+    Clear {
+        /// Only delete time blocks that started before this day, e.g., 2010-12-31 or today
+        #[arg(long, value_parser = parsers::parse_date)]
+        before: Option<NaiveDate>,
+
+        /// Only delete time blocks of this category
+        #[arg(short, long)]
+        category: Option<String>,
+
+        /// Only delete time blocks that started on these days, e.g., 2010-12-01..2010-12-31
+        #[arg(long, value_parser = parsers::parse_date_range)]
+        range: Option<(NaiveDate, NaiveDate)>,
+
+        /// Show what would be deleted without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the time blocks for invalid states, like overlaps, and offer to fix them
+    Doctor {
+        /// Fix all problems without asking
+        #[arg(long)]
+        auto: bool,
+    },
+    /// Revert the last changes made by zyr commands. E.g., zyr undo 2
+    Undo {
+        /// How many changes to revert
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// Apply changes reverted with `zyr undo` again. E.g., zyr redo
+    Redo {
+        /// How many changes to apply again
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// List the recent changes that can be undone
+    History,
     /// View statistics about what you worked on today
     View,
 }
 
 impl Executable for Commands {
-    fn execute(&self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+    fn execute(&self, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
         match self {
-            Commands::Timer { command } => command.execute(data)?,
-            Commands::Plan { command } => command.execute(data)?,
-            Commands::Clear => clear::exec(data),
+            Commands::Timer { command } => command.execute(data, store)?,
+            Commands::Plan { command } => command.execute(data, store)?,
+            Commands::Profile { command } => command.execute(data, store)?,
+            Commands::Trash { command } => command.execute(data, store)?,
+            Commands::Backup { command } => command.execute(data, store)?,
+            Commands::Storage { command } => command.execute(data, store)?,
+            Commands::Config { command } => command.execute(data, store)?,
+            Commands::Template { command } => command.execute(data, store)?,
+            Commands::Clear {
+                before,
+                category,
+                range,
+                dry_run,
+            } => {
+                let scope = clear::Scope {
+                    before: *before,
+                    category: category.as_deref(),
+                    range: *range,
+                };
+                clear::exec(&scope, *dry_run, data, store)?;
+            }
+            Commands::Doctor { auto } => doctor::exec(*auto, data),
+            Commands::Undo { steps } => history::exec_undo(*steps, data, store)?,
+            Commands::Redo { steps } => history::exec_redo(*steps, data, store)?,
+            Commands::History => history::exec_list(store),
             Commands::View => view::exec(data),
         }
         Ok(())
@@ -0,0 +1,115 @@
+use crate::{
+    domain::{Data, Executable, TimeBlock},
+    storage::{self, Store, backups, backups::SnapshotKind},
+    utils::io_utils,
+};
+use clap::Subcommand;
+use std::error::Error;
+
+/// How many time blocks are listed per kind of change in the restore summary.
+const SUMMARY_LINES: usize = 10;
+
+#[derive(Subcommand, PartialEq)]
+pub enum BackupCommands {
+    /// Take a snapshot of the current data that is never removed automatically
+    Create,
+    /// List all snapshots, oldest first
+    List,
+    /// Replace the current data with a snapshot, after showing what would change
+    Restore {
+        /// The id of the snapshot, as shown by `zyr backup list`
+        id: String,
+    },
+}
+
+impl Executable for BackupCommands {
+    fn execute(&self, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Create => Self::exec_create(data, store)?,
+            Self::List => Self::exec_list(store),
+            Self::Restore { id } => Self::exec_restore(id, data, store)?,
+        }
+        Ok(())
+    }
+}
+
+impl BackupCommands {
+    /// Implementation of the `zyr backup create` command
+    fn exec_create(data: &Data, store: &Store) -> Result<(), Box<dyn Error>> {
+        let snapshot = backups::create(store.path(), data, SnapshotKind::Manual)?;
+        println!("Backup {} created", snapshot.id);
+        Ok(())
+    }
+
+    /// Implementation of the `zyr backup list` command
+    fn exec_list(store: &Store) {
+        let snapshots = backups::list(store.path());
+        if snapshots.is_empty() {
+            println!("No backups have been taken yet");
+            return;
+        }
+
+        for snapshot in snapshots {
+            let blocks = match snapshot.load() {
+                Ok(d) => format!("{} time blocks", d.blocks.len()),
+                Err(e) => e.to_string(),
+            };
+            println!(
+                "{:<28} {:<7} {}  {blocks}",
+                snapshot.id,
+                snapshot.kind,
+                snapshot.created.format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+    }
+
+    /// Implementation of the `zyr backup restore` command
+    fn exec_restore(id: &str, data: &mut Data, store: &Store) -> Result<(), Box<dyn Error>> {
+        let snapshot = backups::find(store.path(), id).ok_or_else(|| {
+            format!("Backup {id} does not exist. See `zyr backup list` for all backups.")
+        })?;
+        let restored = snapshot.load()?;
+
+        if restored == *data {
+            println!("The data is identical to backup {id}, nothing to restore");
+            return Ok(());
+        }
+
+        println!("Restoring backup {id} would:");
+        Self::print_changes(
+            "remove",
+            '-',
+            &storage::multiset_difference(&data.blocks, &restored.blocks),
+        );
+        Self::print_changes(
+            "add",
+            '+',
+            &storage::multiset_difference(&restored.blocks, &data.blocks),
+        );
+        if restored.settings != data.settings {
+            println!("  change the settings");
+        }
+
+        if io_utils::confirm("restore this backup") {
+            *data = restored;
+            println!("Backup {id} restored. The previous data was added to the backups.");
+        } else {
+            println!("Backup was not restored");
+        }
+        Ok(())
+    }
+
+    /// Print a summary of the time blocks a restore adds or removes.
+    fn print_changes(verb: &str, marker: char, blocks: &[&TimeBlock]) {
+        if blocks.is_empty() {
+            return;
+        }
+        println!("  {verb} {} time blocks", blocks.len());
+        for block in blocks.iter().take(SUMMARY_LINES) {
+            println!("    {marker} {block}");
+        }
+        if blocks.len() > SUMMARY_LINES {
+            println!("    ... and {} more", blocks.len() - SUMMARY_LINES);
+        }
+    }
+}
@@ -1,11 +1,107 @@
-use crate::{domain::Data, utils::io_utils};
+use crate::{
+    domain::{Data, TimeBlock},
+    storage::{Store, backups, backups::SnapshotKind},
+    utils::{io_utils, time_utils},
+};
+use chrono::{Days, NaiveDate};
+use std::error::Error;
+use std::time::Duration;
+
+/// Which time blocks `zyr clear` removes. Blocks have to match every condition that is set.
+pub struct Scope<'a> {
+    /// Only blocks that started before this day
+    pub before: Option<NaiveDate>,
+    /// Only blocks of this category
+    pub category: Option<&'a str>,
+    /// Only blocks that started between the first and the last of these days
+    pub range: Option<(NaiveDate, NaiveDate)>,
+}
+
+impl Scope<'_> {
+    /// Whether no condition is set, so that all data is cleared.
+    fn is_everything(&self) -> bool {
+        self.before.is_none() && self.category.is_none() && self.range.is_none()
+    }
+
+    fn matches(&self, block: &TimeBlock) -> bool {
+        let before = self
+            .before
+            .is_none_or(|d| block.start_unix < time_utils::start_of_day(d));
+        let category = self.category.is_none_or(|c| block.category == c);
+        let range = self.range.is_none_or(|(from, to)| {
+            let end = time_utils::start_of_day(to + Days::new(1));
+            (time_utils::start_of_day(from)..end).contains(&block.start_unix)
+        });
+        before && category && range
+    }
+}
 
 /// Implementation of the `zyr clear` command
-pub fn exec(data: &mut Data) {
-    if io_utils::confirm("delete all data") {
+pub fn exec(
+    scope: &Scope,
+    dry_run: bool,
+    data: &mut Data,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    if scope.is_everything() {
+        return exec_everything(dry_run, data, store);
+    }
+
+    let affected: Vec<&TimeBlock> = data.blocks.iter().filter(|b| scope.matches(b)).collect();
+    if affected.is_empty() {
+        println!("No time blocks match, nothing to delete");
+        return Ok(());
+    }
+
+    let now = time_utils::since_unix().as_millis() as u64;
+    let total: u64 = affected
+        .iter()
+        .map(|b| b.end_unix.unwrap_or(now).saturating_sub(b.start_unix))
+        .sum();
+    let summary = format!(
+        "{} time block{} with a total duration of {}",
+        affected.len(),
+        if affected.len() == 1 { "" } else { "s" },
+        time_utils::prettify_duration(Duration::from_millis(total))
+    );
+
+    if dry_run {
+        for block in &affected {
+            println!("{block}");
+        }
+        println!("Would delete {summary}");
+        return Ok(());
+    }
+
+    if io_utils::confirm(&format!("delete {summary}")) {
+        let snapshot = backups::create(store.path(), data, SnapshotKind::Clear)?;
+        data.blocks.retain(|b| !scope.matches(b));
+        println!(
+            "Deleted {summary}. Undo this with `zyr undo` or `zyr backup restore {}`",
+            snapshot.id
+        );
+    } else {
+        println!("Data was not deleted");
+    }
+    Ok(())
+}
+
+/// Delete all data, including the settings and the trash.
+fn exec_everything(dry_run: bool, data: &mut Data, store: &Store) -> Result<(), Box<dyn Error>> {
+    if dry_run {
+        println!(
+            "Would delete all data, including {} time blocks, the trash and the settings",
+            data.blocks.len()
+        );
+    } else if io_utils::confirm("delete all data") {
+        let snapshot = backups::create(store.path(), data, SnapshotKind::Clear)?;
         *data = Data::empty();
-        println!("All data has been deleted");
+        println!(
+            "All data has been deleted. Undo this with `zyr backup restore {}`",
+            snapshot.id
+        );
     } else {
         println!("Data was not deleted");
     }
+    Ok(())
 }
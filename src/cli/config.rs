@@ -0,0 +1,61 @@
+use crate::{
+    domain::{Data, Executable, Settings},
+    storage::Store,
+};
+use clap::Subcommand;
+use serde_json::Value;
+use std::error::Error;
+
+#[derive(Subcommand, PartialEq)]
+pub enum ConfigCommands {
+    /// Show all settings and their values
+    List,
+    /// Change a setting. E.g., zyr config set backup_days 30
+    Set {
+        /// The name of the setting, as shown by `zyr config list`
+        key: String,
+
+        /// The new value of the setting
+        value: String,
+    },
+}
+
+impl Executable for ConfigCommands {
+    fn execute(&self, data: &mut Data, _store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::List => Self::exec_list(data)?,
+            Self::Set { key, value } => Self::exec_set(key, value, data)?,
+        }
+        Ok(())
+    }
+}
+
+impl ConfigCommands {
+    /// Implementation of the `zyr config list` command
+    fn exec_list(data: &Data) -> Result<(), Box<dyn Error>> {
+        if let Value::Object(settings) = serde_json::to_value(&data.settings)? {
+            for (key, value) in settings {
+                println!("{key} = {value}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr config set` command
+    fn exec_set(key: &str, value: &str, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        let mut settings = serde_json::to_value(&data.settings)?;
+        let slot = settings.get_mut(key).ok_or_else(|| {
+            format!("Unknown setting {key}. See `zyr config list` for all settings.")
+        })?;
+
+        // Values that are not valid JSON, like most words, are treated as strings
+        *slot = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+        let settings: Settings = serde_json::from_value(settings)
+            .map_err(|e| format!("Invalid value for {key}: {e}"))?;
+        settings.work_hours()?;
+        data.settings = settings;
+
+        println!("{key} set to {value}");
+        Ok(())
+    }
+}
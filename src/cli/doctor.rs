@@ -0,0 +1,395 @@
+use crate::{
+    domain::{Data, TimeBlock},
+    utils::{io_utils, time_utils},
+};
+use std::fmt;
+use std::time::Duration;
+
+/// Blocks starting further than this in the future are most likely typos.
+const FAR_FUTURE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Blocks lasting longer than this are most likely timers that were never ended.
+const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// An invalid state of the time blocks. Blocks are referred to by id, so that problems stay
+/// valid while others are being fixed.
+#[derive(Debug, PartialEq)]
+enum Problem {
+    EndBeforeStart {
+        id: u64,
+    },
+    /// More than one block has no end. All but `running` are ended when the next tracked block
+    /// starts, at most a day after they started.
+    MultipleOpenEnded {
+        running: u64,
+        others: Vec<u64>,
+    },
+    /// `first` starts before `second`. If it also ends after it, it `encloses` it.
+    Overlap {
+        first: u64,
+        second: u64,
+        encloses: bool,
+    },
+    FarFuture {
+        id: u64,
+    },
+    AbsurdDuration {
+        id: u64,
+    },
+    /// A block without an end that started more than a day ago. It is ended at `end`, which is
+    /// when the next block starts, or a day after it started.
+    ForgottenTimer {
+        id: u64,
+        end: u64,
+    },
+}
+
+impl Problem {
+    /// Describe how the problem is fixed, phrased to complete "Are you sure you want to".
+    fn repair(&self) -> String {
+        match self {
+            Self::EndBeforeStart { id } => format!("swap the start and end of block {id}"),
+            Self::MultipleOpenEnded { running, .. } => {
+                format!("end all open blocks except {running} when the next tracked block starts")
+            }
+            Self::Overlap {
+                first,
+                second,
+                encloses: false,
+            } => format!("trim the end of block {first} to the start of block {second}"),
+            Self::Overlap {
+                first,
+                second,
+                encloses: true,
+            } => format!(
+                "split block {first} into the parts before and after block {second}, dropping the time they share"
+            ),
+            Self::FarFuture { id } | Self::AbsurdDuration { id } => {
+                format!("move block {id} to the trash")
+            }
+            Self::ForgottenTimer { id, end } => format!(
+                "end block {id} at {}",
+                time_utils::convert(*end).format("%Y-%m-%d %H:%M")
+            ),
+        }
+    }
+
+    /// Apply the repair. Does nothing if the problem no longer exists, e.g. because an earlier
+    /// repair removed one of its blocks.
+    fn fix(&self, data: &mut Data) {
+        let now = time_utils::since_unix().as_millis() as u64;
+        match self {
+            Self::EndBeforeStart { id } => {
+                if let Some(i) = data.block_index(*id)
+                    && let Some(end) = data.blocks[i].end_unix
+                    && end < data.blocks[i].start_unix
+                {
+                    let block = &mut data.blocks[i];
+                    block.end_unix = Some(block.start_unix);
+                    block.start_unix = end;
+                }
+            }
+            Self::MultipleOpenEnded { others, .. } => {
+                for id in others {
+                    let Some(i) = data.block_index(*id) else {
+                        continue;
+                    };
+                    let start = data.blocks[i].start_unix;
+                    let latest = now.min(start + MAX_DURATION.as_millis() as u64);
+                    let end = data
+                        .blocks
+                        .iter()
+                        .filter(|b| !b.planned && b.start_unix > start)
+                        .map(|b| b.start_unix)
+                        .min()
+                        .map_or(latest, |next_start| next_start.min(latest));
+                    data.blocks[i].end_unix.get_or_insert(end);
+                }
+            }
+            Self::Overlap { first, second, .. } => {
+                if let (Some(i), Some(j)) = (data.block_index(*first), data.block_index(*second)) {
+                    let (second_start, second_end) =
+                        (data.blocks[j].start_unix, data.blocks[j].end_or(now));
+                    let first = &data.blocks[i];
+                    if first.end_or(now) <= second_start || first.start_unix > second_start {
+                        return;
+                    }
+
+                    // Keep the part of an enclosing block after the enclosed one
+                    let rest = (first.end_or(now) > second_end).then(|| TimeBlock {
+                        id: data.next_block_id(),
+                        start_unix: second_end,
+                        ..first.clone()
+                    });
+                    data.blocks[i].end_unix = Some(second_start);
+                    if let Some(rest) = rest {
+                        data.insert_block(rest);
+                    }
+                }
+            }
+            Self::FarFuture { id } | Self::AbsurdDuration { id } => {
+                if let Some(i) = data.block_index(*id) {
+                    data.trash_block(i);
+                }
+            }
+            Self::ForgottenTimer { id, end } => {
+                if let Some(i) = data.block_index(*id) {
+                    data.blocks[i].end_unix.get_or_insert(*end);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EndBeforeStart { id } => write!(f, "Block {id} ends before it starts"),
+            Self::MultipleOpenEnded { running, others } => {
+                let ids: Vec<String> = others.iter().map(u64::to_string).collect();
+                write!(
+                    f,
+                    "Blocks {} and {running} have no end, but only one timer can run",
+                    ids.join(", ")
+                )
+            }
+            Self::Overlap { first, second, .. } => {
+                write!(f, "Blocks {first} and {second} overlap")
+            }
+            Self::FarFuture { id } => write!(f, "Block {id} starts more than a year from now"),
+            Self::AbsurdDuration { id } => write!(f, "Block {id} lasts longer than a day"),
+            Self::ForgottenTimer { id, .. } => {
+                write!(f, "Block {id} has been running for more than a day")
+            }
+        }
+    }
+}
+
+/// Implementation of the `zyr doctor` command
+pub fn exec(auto: bool, data: &mut Data) {
+    let problems = find_problems(data);
+    if problems.is_empty() {
+        println!("No problems found");
+        return;
+    }
+
+    println!("Found {} problem(s):", problems.len());
+    for problem in &problems {
+        println!("  {problem}");
+    }
+
+    let mut fixed = 0;
+    for problem in &problems {
+        if auto || io_utils::confirm(&problem.repair()) {
+            problem.fix(data);
+            fixed += 1;
+        }
+    }
+
+    let remaining = find_problems(data).len();
+    println!("Fixed {fixed} problem(s), {remaining} remaining");
+}
+
+/// Check the time blocks for every kind of problem.
+fn find_problems(data: &Data) -> Vec<Problem> {
+    let now = time_utils::since_unix().as_millis() as u64;
+    let mut problems = vec![];
+
+    for block in &data.blocks {
+        if block.end_unix.is_some_and(|end| end < block.start_unix) {
+            problems.push(Problem::EndBeforeStart { id: block.id });
+        }
+    }
+
+    let mut by_start: Vec<&TimeBlock> = data.blocks.iter().collect();
+    by_start.sort_by_key(|b| b.start_unix);
+
+    let open: Vec<u64> = by_start
+        .iter()
+        .filter(|b| b.end_unix.is_none())
+        .map(|b| b.id)
+        .collect();
+    if let Some((&running, others)) = open.split_last()
+        && !others.is_empty()
+    {
+        problems.push(Problem::MultipleOpenEnded {
+            running,
+            others: others.to_vec(),
+        });
+    }
+
+    // The block reaching furthest so far overlaps every later block starting before its end.
+    // Planned blocks are allowed to overlap tracked ones.
+    for planned in [false, true] {
+        let mut furthest: Option<&TimeBlock> = None;
+        for &block in by_start.iter().filter(|b| b.planned == planned) {
+            if let Some(prev) = furthest
+                && block.start_unix < prev.end_or(now)
+                && block.start_unix < block.end_or(now)
+            {
+                problems.push(Problem::Overlap {
+                    first: prev.id,
+                    second: block.id,
+                    encloses: prev.end_or(now) > block.end_or(now),
+                });
+            }
+            if furthest.is_none_or(|prev| block.end_or(now) > prev.end_or(now)) {
+                furthest = Some(block);
+            }
+        }
+    }
+
+    let far_future = now + FAR_FUTURE.as_millis() as u64;
+    let max_duration = MAX_DURATION.as_millis() as u64;
+    for block in &data.blocks {
+        if block.start_unix > far_future {
+            problems.push(Problem::FarFuture { id: block.id });
+        } else if block.end_or(now).saturating_sub(block.start_unix) <= max_duration {
+            continue;
+        } else if block.end_unix.is_none() {
+            // A timer that was never ended, which most likely lasted until the next block
+            let next_start = by_start
+                .iter()
+                .filter(|b| !b.planned && b.start_unix > block.start_unix)
+                .map(|b| b.start_unix)
+                .next();
+            let end = next_start.map_or(block.start_unix + max_duration, |start| {
+                start.min(block.start_unix + max_duration)
+            });
+            problems.push(Problem::ForgottenTimer { id: block.id, end });
+        } else {
+            problems.push(Problem::AbsurdDuration { id: block.id });
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(id: u64, start_unix: u64, end_unix: Option<u64>) -> TimeBlock {
+        TimeBlock {
+            id,
+            start_unix,
+            end_unix,
+            category: "code".to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    #[test]
+    fn test_find_problems_detects_each_kind() {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut data = Data::new();
+        data.blocks = vec![
+            block(1, now - 5000, Some(now - 6000)),
+            block(2, now - 9000, Some(now - 7000)),
+            block(3, now - 8000, Some(now - 7500)),
+            block(4, now - 4000, None),
+            block(5, now - 3000, None),
+        ];
+
+        let problems = find_problems(&data);
+        assert!(problems.contains(&Problem::EndBeforeStart { id: 1 }));
+        assert!(problems.contains(&Problem::Overlap {
+            first: 2,
+            second: 3,
+            encloses: true
+        }));
+        assert!(problems.contains(&Problem::MultipleOpenEnded {
+            running: 5,
+            others: vec![4]
+        }));
+    }
+
+    #[test]
+    fn test_fixes_leave_no_problems() {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut data = Data::new();
+        data.blocks = vec![
+            block(1, now - 5000, Some(now - 6000)),
+            block(2, now - 9000, Some(now - 7000)),
+            block(3, now - 8000, Some(now - 7500)),
+            block(4, now - 4000, None),
+            block(5, now - 3000, None),
+        ];
+
+        for problem in find_problems(&data) {
+            problem.fix(&mut data);
+        }
+        assert_eq!(find_problems(&data), vec![]);
+    }
+
+    #[test]
+    fn test_overlap_fix_keeps_rest_of_enclosing_block() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, Some(1000)), block(2, 200, Some(300))];
+
+        for problem in find_problems(&data) {
+            problem.fix(&mut data);
+        }
+        let spans: Vec<(u64, u64, Option<u64>)> = data
+            .blocks
+            .iter()
+            .map(|b| (b.id, b.start_unix, b.end_unix))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(1, 0, Some(200)), (2, 200, Some(300)), (3, 300, Some(1000))]
+        );
+    }
+
+    #[test]
+    fn test_forgotten_timer_is_ended() {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let day = MAX_DURATION.as_millis() as u64;
+        let mut data = Data::new();
+        data.blocks = vec![block(1, now - 3 * day, None)];
+
+        let problems = find_problems(&data);
+        let end = now - 2 * day;
+        assert_eq!(problems, vec![Problem::ForgottenTimer { id: 1, end }]);
+        problems[0].fix(&mut data);
+        assert_eq!(data.blocks[0].end_unix, Some(end));
+        assert!(data.trash.is_empty());
+    }
+
+    #[test]
+    fn test_open_blocks_end_before_next_tracked_block() {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut data = Data::new();
+        data.blocks = vec![
+            block(1, now - 9000, None),
+            TimeBlock {
+                planned: true,
+                ..block(2, now - 8000, Some(now - 7000))
+            },
+            block(3, now - 5000, None),
+        ];
+
+        for problem in find_problems(&data) {
+            problem.fix(&mut data);
+        }
+        assert_eq!(data.blocks[0].end_unix, Some(now - 5000));
+
+        // Without a later tracked block, the open block is ended now instead of when it started
+        let mut data = Data::new();
+        data.blocks = vec![
+            block(1, now - 9000, None),
+            TimeBlock {
+                planned: true,
+                ..block(2, now - 5000, None)
+            },
+        ];
+        Problem::MultipleOpenEnded {
+            running: 2,
+            others: vec![1],
+        }
+        .fix(&mut data);
+        assert!(data.blocks[0].end_unix.is_some_and(|end| end >= now));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::{
+    domain::Data,
+    storage::{
+        Store,
+        history::{Entry, History},
+    },
+    utils::time_utils,
+};
+use std::error::Error;
+
+/// Implementation of the `zyr undo` command
+pub fn exec_undo(steps: usize, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+    for entry in store.undo(data, steps)? {
+        println!("Undid `{}` ({})", entry.command, entry.summary());
+    }
+    Ok(())
+}
+
+/// Implementation of the `zyr redo` command
+pub fn exec_redo(steps: usize, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+    for entry in store.redo(data, steps)? {
+        println!("Redid `{}` ({})", entry.command, entry.summary());
+    }
+    Ok(())
+}
+
+/// Implementation of the `zyr history` command
+pub fn exec_list(store: &Store) {
+    let history = History::load(store.path());
+    if history.applied().is_empty() && history.undone().is_empty() {
+        println!("No changes have been recorded yet");
+        return;
+    }
+
+    // Most recent first, with the undone changes on top
+    for entry in history.undone().iter().rev() {
+        print_entry(entry, "undone");
+    }
+    for entry in history.applied().iter().rev() {
+        print_entry(entry, "");
+    }
+}
+
+fn print_entry(entry: &Entry, mark: &str) {
+    println!(
+        "{}  {:<6}  {}  ({})",
+        time_utils::convert(entry.at).format("%Y-%m-%d %H:%M:%S"),
+        mark,
+        entry.command,
+        entry.summary()
+    );
+}
@@ -1,16 +1,19 @@
+use super::recur::RecurCommands;
 use crate::{
-    domain::{Data, Executable, TimeBlock},
+    domain::{BlockRef, Data, Executable, OverlapPolicy, TimeBlock},
+    storage::Store,
     terminal::{FRAME_DURATION_MS, PAGE_SIZE, RawTerminal},
     utils::{io_utils, parsers, time_utils},
 };
-use chrono::{DateTime, Local};
-use clap::{ArgAction, Subcommand};
+use chrono::{DateTime, Days, Local, NaiveDate};
+use clap::{ArgAction, Args, Subcommand};
 use crossterm::{
     cursor,
     event::{self, Event, KeyCode, KeyModifiers},
     execute,
     style::{self, Color},
 };
+use std::collections::BTreeMap;
 use std::time::Duration;
 use std::{error::Error, io};
 
@@ -34,75 +37,455 @@ pub enum PlanCommands {
         /// Either specify a duration or an end time.
         #[arg(short, long, value_parser = parsers::parse_timestamp)]
         to: Option<DateTime<Local>>,
+
+        #[command(flatten)]
+        labels: BlockLabels,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
     },
     /// Modify a pre-existing time block
     Edit {
-        /// The updated category. E.g., code, study, break
-        #[arg(short, long)]
-        category: Option<String>,
+        #[command(flatten)]
+        changes: BlockChanges,
 
-        /// The updated start time of the block, e.g., 2010-12-31T10:00:00
-        #[arg(short, long, value_parser = parsers::parse_timestamp)]
-        from: Option<DateTime<Local>>,
+        /// 0 for most recent, 1 for second most recent, etc., p0 for the most recent planned block,
+        /// or the id of the block, e.g., #12.
+        /// Omitting this opens interactive mode.
+        block: Option<BlockRef>,
 
-        /// The updated duration of the block, e.g., 1h10m20s.
-        /// Either specify a duration or an end time.
-        #[arg(short, long, value_parser = parsers::parse_duration)]
-        duration: Option<Duration>,
-
-        /// The updated end time of the block, e.g., 2010-12-31T10:00:00.
-        /// Either specify a duration or an end time.
-        #[arg(short, long, value_parser = parsers::parse_timestamp)]
-        to: Option<DateTime<Local>>,
-
-        /// 0 for most recent, 1 for second most recent, etc.
-        /// Omitting this number opens interactive mode.
-        order_number: Option<u32>,
-
-        /// Edit the most recent time block.
-        /// Same as writing the number 0 for `ORDER_NUMBER`.
+        /// Edit the most recent tracked time block.
+        /// Same as writing the number 0 for `BLOCK`.
         #[arg(short, long, action = ArgAction::SetTrue)]
         last: bool,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
     },
     /// Delete a time block
     Del {
-        /// 0 for most recent, 1 for second most recent, etc.
-        /// Omitting this number opens interactive mode.
-        order_number: Option<u32>,
+        /// 0 for most recent, 1 for second most recent, etc., p0 for the most recent planned block,
+        /// or the id of the block, e.g., #12.
+        /// Omitting this opens interactive mode.
+        block: Option<BlockRef>,
 
-        /// Delete the most recent time block.
-        /// Same as writing the number 0 for `ORDER_NUMBER`.
+        /// Delete the most recent tracked time block.
+        /// Same as writing the number 0 for `BLOCK`.
         #[arg(short, long, action = ArgAction::SetTrue)]
         last: bool,
     },
+    /// Cut a time block into two at a given moment. E.g., zyr plan split 0 --at 10:30 -c review
+    Split {
+        /// 0 for most recent, 1 for second most recent, etc., p0 for the most recent planned block,
+        /// or the id of the block, e.g., #12.
+        /// Omitting this opens interactive mode.
+        block: Option<BlockRef>,
+
+        /// Where to cut the block, e.g., 10:30 or 2010-12-31T10:30:00.
+        /// A time of day refers to the day the block starts on.
+        #[arg(long)]
+        at: String,
+
+        /// The category of the second part. Defaults to the category of the block.
+        #[arg(short, long)]
+        category: Option<String>,
+
+        /// The note of the second part. Defaults to the note of the block.
+        #[arg(short, long)]
+        note: Option<String>,
+    },
+    /// Combine time blocks into one that spans all of them. E.g., zyr plan merge 0 1 2
+    Merge {
+        /// Two or more order numbers or ids of blocks, e.g., 0 1, p0 p1 or #12 #13
+        #[arg(required_unless_present = "auto")]
+        blocks: Vec<BlockRef>,
+
+        /// Merge all consecutive blocks of the same category that are at most `--gap` apart
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with_all = ["blocks", "category"])]
+        auto: bool,
+
+        /// The largest gap between two blocks that `--auto` merges, e.g., 5m
+        #[arg(long, value_parser = parsers::parse_duration, default_value = "5m")]
+        gap: Duration,
+
+        /// The category of the merged block. Defaults to the category of the earliest block.
+        #[arg(short, long)]
+        category: Option<String>,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
+    },
+    /// Move a time block by a relative offset. E.g., zyr plan shift 0 -1h --cascade
+    Shift {
+        /// 0 for most recent, 1 for second most recent, etc., p0 for the most recent planned block,
+        /// or the id of the block, e.g., #12
+        block: BlockRef,
+
+        /// How far to move the block, e.g., +15m or -1h
+        #[arg(allow_hyphen_values = true, value_parser = parsers::parse_offset)]
+        offset: i64,
+
+        /// Also move all later time blocks that start on the same day.
+        /// Only tracked blocks move with a tracked block, and planned ones with a planned block.
+        #[arg(long, action = ArgAction::SetTrue)]
+        cascade: bool,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
+    },
+    /// List the untracked time within the working hours of a day.
+    /// Set the working hours with zyr config set work_start 09:00
+    Gaps {
+        /// The day to check, e.g., 2010-12-31 or yesterday. Defaults to today.
+        #[arg(long, value_parser = parsers::parse_date)]
+        date: Option<NaiveDate>,
+    },
+    /// Show the plan of a day next to the tracked time and how closely the plan was followed
+    Agenda {
+        /// The day to show, e.g., 2010-12-31 or yesterday. Defaults to today.
+        #[arg(long, value_parser = parsers::parse_date)]
+        date: Option<NaiveDate>,
+    },
+    /// Walk through the untracked time of a day and create time blocks for it
+    Fill {
+        /// The day to fill, e.g., 2010-12-31 or yesterday. Defaults to today.
+        #[arg(long, value_parser = parsers::parse_date)]
+        date: Option<NaiveDate>,
+    },
+    /// Recategorize, move or delete all time blocks that match a selection.
+    /// E.g., zyr plan bulk --category code --from monday --to friday --set-category website
+    Bulk {
+        #[command(flatten)]
+        selection: BlockSelection,
+
+        #[command(flatten)]
+        action: BulkAction,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
+    },
+    /// Copy a time block onto another day at the same time. E.g., zyr plan copy 0 --to-date tomorrow
+    Copy {
+        /// 0 for most recent, 1 for second most recent, etc., p0 for the most recent planned block,
+        /// or the id of the block, e.g., #12
+        block: BlockRef,
+
+        /// The day of the copy, e.g., 2010-12-31 or tomorrow
+        #[arg(long, value_parser = parsers::parse_date)]
+        to_date: NaiveDate,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
+    },
+    /// Copy all time blocks of a day onto another day at the same times.
+    /// E.g., zyr plan copy-day yesterday today
+    CopyDay {
+        /// The day to copy, e.g., 2010-12-31 or yesterday
+        #[arg(value_parser = parsers::parse_date)]
+        src: NaiveDate,
+
+        /// The day to copy the time blocks to, e.g., 2010-12-31 or today
+        #[arg(value_parser = parsers::parse_date)]
+        dst: NaiveDate,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
+    },
+    /// Manage time blocks that repeat on certain days, like a daily stand-up
+    Recur {
+        #[command(subcommand)]
+        command: RecurCommands,
+    },
+}
+
+/// Which time blocks `zyr plan bulk` changes. Blocks have to match every condition that is set.
+#[derive(Args, PartialEq)]
+pub struct BlockSelection {
+    /// Only blocks of this category
+    #[arg(short, long)]
+    category: Option<String>,
+
+    /// Only blocks with this tag
+    #[arg(long)]
+    tag: Option<String>,
+
+    /// Only blocks that start on or after this day, e.g., 2010-12-31 or monday
+    #[arg(long, value_parser = parsers::parse_date)]
+    from: Option<NaiveDate>,
+
+    /// Only blocks that start on or before this day, e.g., 2010-12-31 or friday
+    #[arg(long, value_parser = parsers::parse_date)]
+    to: Option<NaiveDate>,
+}
+
+impl BlockSelection {
+    fn is_empty(&self) -> bool {
+        self.category.is_none() && self.tag.is_none() && self.from.is_none() && self.to.is_none()
+    }
+
+    fn matches(&self, block: &TimeBlock) -> bool {
+        let category = self.category.as_ref().is_none_or(|c| &block.category == c);
+        let tag = self.tag.as_ref().is_none_or(|t| block.tags.contains(t));
+        let from = self
+            .from
+            .is_none_or(|d| block.start_unix >= time_utils::start_of_day(d));
+        let to = self
+            .to
+            .is_none_or(|d| block.start_unix < time_utils::start_of_day(d + Days::new(1)));
+        category && tag && from && to
+    }
+}
+
+/// What `zyr plan bulk` does with the selected time blocks. Exactly one action is required.
+#[derive(Args, PartialEq)]
+#[group(required = true, multiple = false)]
+pub struct BulkAction {
+    /// Change the category of the blocks
+    #[arg(long)]
+    set_category: Option<String>,
+
+    /// Move the blocks by a relative offset, e.g., +15m or -1h
+    #[arg(long, allow_hyphen_values = true, value_parser = parsers::parse_offset)]
+    shift: Option<i64>,
+
+    /// Move the blocks to the trash
+    #[arg(long, action = ArgAction::SetTrue)]
+    delete: bool,
+}
+
+/// The description, tags and kind of a new time block.
+#[derive(Args, PartialEq)]
+pub struct BlockLabels {
+    /// A description of what was done
+    #[arg(short, long)]
+    note: Option<String>,
+
+    /// A label that groups blocks across categories, e.g., a project. Can be repeated.
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+
+    /// Add the block to the schedule instead of the tracked time.
+    /// Blocks that start in the future are always planned.
+    #[arg(long, action = ArgAction::SetTrue)]
+    planned: bool,
+}
+
+/// The changes `zyr plan edit` makes to a time block.
+#[derive(Args, PartialEq)]
+pub struct BlockChanges {
+    /// The updated category. E.g., code, study, break
+    #[arg(short, long)]
+    category: Option<String>,
+
+    /// The updated start time of the block, e.g., 2010-12-31T10:00:00
+    #[arg(short, long, value_parser = parsers::parse_timestamp)]
+    from: Option<DateTime<Local>>,
+
+    /// Move the start of the block by a relative offset, e.g., +15m or -1h
+    #[arg(long, allow_hyphen_values = true, value_parser = parsers::parse_offset, conflicts_with = "from")]
+    start_by: Option<i64>,
+
+    /// Move the end of the block by a relative offset, e.g., +15m or -1h
+    #[arg(long, allow_hyphen_values = true, value_parser = parsers::parse_offset, conflicts_with_all = ["duration", "to"])]
+    end_by: Option<i64>,
+
+    /// The updated duration of the block, e.g., 1h10m20s.
+    /// Either specify a duration or an end time.
+    #[arg(short, long, value_parser = parsers::parse_duration)]
+    duration: Option<Duration>,
+
+    /// The updated end time of the block, e.g., 2010-12-31T10:00:00.
+    /// Either specify a duration or an end time.
+    #[arg(short, long, value_parser = parsers::parse_timestamp)]
+    to: Option<DateTime<Local>>,
+
+    /// The updated description of what was done. An empty note removes it.
+    #[arg(short, long)]
+    note: Option<String>,
+
+    /// Add a tag to the block. Can be repeated.
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+
+    /// Remove a tag from the block. Can be repeated.
+    #[arg(long = "untag")]
+    untags: Vec<String>,
+
+    /// Move the block to the schedule, e.g., to compare it with what actually happened
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "tracked")]
+    planned: bool,
+
+    /// Move a planned block to the tracked time, e.g., because it happened as planned
+    #[arg(long, action = ArgAction::SetTrue)]
+    tracked: bool,
+}
+
+impl BlockChanges {
+    fn is_empty(&self) -> bool {
+        self.category.is_none()
+            && self.from.is_none()
+            && self.start_by.is_none()
+            && self.end_by.is_none()
+            && self.duration.is_none()
+            && self.to.is_none()
+            && self.note.is_none()
+            && self.tags.is_empty()
+            && self.untags.is_empty()
+            && !self.planned
+            && !self.tracked
+    }
+
+    /// Apply the changes to a copy of `block`.
+    ///
+    /// * return - The changed block, or an error if the changes contradict each other
+    fn apply(&self, block: &TimeBlock) -> Result<TimeBlock, String> {
+        let mut block = block.clone();
+        let out_of_range = || "The time block cannot be moved that far".to_string();
+        let start_unix = match (self.from, self.start_by) {
+            (Some(dt), _) => dt.timestamp_millis() as u64,
+            (None, Some(offset)) => block
+                .start_unix
+                .checked_add_signed(offset)
+                .ok_or_else(out_of_range)?,
+            (None, None) => block.start_unix,
+        };
+        let end_unix = match (self.duration, self.to) {
+            (None, None) => block.end_unix,
+            (Some(_), Some(_)) => {
+                return Err(
+                "The time block duration and end time cannot both be set at the same time! Please choose only one of them.".into()
+            );
+            }
+            (Some(d), None) => {
+                Some((time_utils::convert(start_unix) + d).timestamp_millis() as u64)
+            }
+            (None, Some(t)) => Some(t.timestamp_millis() as u64),
+        };
+        let end_unix = match (end_unix, self.end_by) {
+            (None, Some(_)) => {
+                return Err("The time block is still running, so its end cannot be moved".into());
+            }
+            (Some(end), Some(offset)) => {
+                Some(end.checked_add_signed(offset).ok_or_else(out_of_range)?)
+            }
+            (end, None) => end,
+        };
+        if end_unix.is_some_and(|end| end < start_unix) {
+            return Err("The time block would end before it starts".into());
+        }
+
+        block.start_unix = start_unix;
+        block.end_unix = end_unix;
+        if let Some(c) = &self.category {
+            block.category = c.to_string();
+        }
+        if let Some(n) = &self.note {
+            block.note = Some(n.to_string()).filter(|n| !n.is_empty());
+        }
+        for tag in &self.tags {
+            if !block.tags.contains(tag) {
+                block.tags.push(tag.to_string());
+            }
+        }
+        block.tags.retain(|t| !self.untags.contains(t));
+        if self.planned || self.tracked {
+            block.planned = self.planned;
+        }
+        Ok(block)
+    }
+}
+
+/// How to treat existing time blocks that overlap a new or moved one
+#[derive(Args, PartialEq)]
+pub struct OverlapArgs {
+    /// What to do with existing time blocks that overlap this one
+    #[arg(long, value_enum, default_value_t = OverlapPolicy::Reject)]
+    on_overlap: OverlapPolicy,
+
+    /// Keep existing time blocks that overlap this one as they are.
+    /// Same as --on-overlap allow.
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "on_overlap")]
+    allow_overlap: bool,
+}
+
+impl OverlapArgs {
+    pub fn policy(&self) -> OverlapPolicy {
+        if self.allow_overlap {
+            OverlapPolicy::Allow
+        } else {
+            self.on_overlap
+        }
+    }
 }
 
 impl Executable for PlanCommands {
-    fn execute(&self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+    fn execute(&self, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
         match self {
             Self::Add {
                 category,
                 from,
                 duration,
                 to,
-            } => Self::exec_add(category, *from, *duration, *to, data)?,
-            Self::Edit {
+                labels,
+                overlap,
+            } => Self::exec_add(
                 category,
-                from,
-                duration,
-                to,
-                order_number,
-                last,
-            } => Self::exec_edit(
-                category.as_ref(),
                 *from,
                 *duration,
                 *to,
-                *order_number,
-                *last,
+                labels,
+                overlap.policy(),
                 data,
             )?,
-            Self::Del { order_number, last } => Self::exec_del(*order_number, *last, data)?,
+            Self::Edit {
+                changes,
+                block,
+                last,
+                overlap,
+            } => Self::exec_edit(changes, *block, *last, overlap.policy(), data)?,
+            Self::Del { block, last } => Self::exec_del(*block, *last, data)?,
+            Self::Split {
+                block,
+                at,
+                category,
+                note,
+            } => Self::exec_split(*block, at, category.as_deref(), note.as_deref(), data)?,
+            Self::Merge {
+                blocks,
+                auto: false,
+                category,
+                overlap,
+                ..
+            } => Self::exec_merge(blocks, category.as_deref(), overlap.policy(), data)?,
+            Self::Merge {
+                auto: true,
+                gap,
+                overlap,
+                ..
+            } => Self::exec_merge_auto(*gap, overlap.policy(), data)?,
+            Self::Shift {
+                block,
+                offset,
+                cascade,
+                overlap,
+            } => Self::exec_shift(*block, *offset, *cascade, overlap.policy(), data)?,
+            Self::Gaps { date } => Self::exec_gaps(*date, data)?,
+            Self::Agenda { date } => Self::exec_agenda(*date, data),
+            Self::Fill { date } => Self::exec_fill(*date, data)?,
+            Self::Bulk {
+                selection,
+                action,
+                overlap,
+            } => Self::exec_bulk(selection, action, overlap.policy(), data)?,
+            Self::Copy {
+                block,
+                to_date,
+                overlap,
+            } => Self::exec_copy(*block, *to_date, overlap.policy(), data)?,
+            Self::CopyDay { src, dst, overlap } => {
+                Self::exec_copy_day(*src, *dst, overlap.policy(), data)?
+            }
+            Self::Recur { command } => command.execute(data, store)?,
         }
         Ok(())
     }
@@ -115,6 +498,8 @@ impl PlanCommands {
         from: DateTime<Local>,
         duration: Option<Duration>,
         to: Option<DateTime<Local>>,
+        labels: &BlockLabels,
+        policy: OverlapPolicy,
         data: &mut Data,
     ) -> Result<(), Box<dyn Error>> {
         let end_unix = match (duration, to) {
@@ -129,42 +514,46 @@ impl PlanCommands {
             (Some(d), None) => (from + d).timestamp_millis() as u64,
             (None, Some(t)) => t.timestamp_millis() as u64,
         };
+        let start_unix = from.timestamp_millis() as u64;
+        if end_unix < start_unix {
+            return Err("The time block would end before it starts".into());
+        }
 
         let tb = TimeBlock {
-            start_unix: from.timestamp_millis() as u64,
+            id: data.next_block_id(),
+            start_unix,
             end_unix: Some(end_unix),
             category: category.to_string(),
+            note: labels.note.clone(),
+            tags: labels.tags.clone(),
+            planned: labels.planned || from > Local::now(),
         };
-        data.blocks.push(tb);
+        data.put_block(tb, policy)?;
 
         Ok(())
     }
 
     /// Calculate the time block index given the CLI arguments.
     ///
-    /// * `order_number` - The order number provided by the user
-    /// * `last`         - Whether the user used the --last flag
-    /// * `data`         - The user's data
+    /// * `block` - The order number or id provided by the user
+    /// * `last`  - Whether the user used the --last flag
+    /// * `data`  - The user's data
     ///
     /// * return - The index in the array of time blocks, or an error
-    fn get_index_from_order_number(
-        order_number: Option<u32>,
+    fn get_index(
+        block: Option<BlockRef>,
         last: bool,
         data: &mut Data,
     ) -> Result<usize, Box<dyn Error>> {
-        let computed_order = match (order_number, last) {
-            (None, false) => Self::choose_index(data)?,
-            (Some(0) | None, true) => 0,
+        let block = match (block, last) {
+            (None, false) => Self::choose_block(data)?,
+            (Some(BlockRef::Order(0)) | None, true) => BlockRef::Order(0),
             (_, true) => {
                 return Err("Mismatched order numbers! Either manually provide the order number or use --last, but not both at the same time.".into());
             }
-            (Some(x), false) => x,
+            (Some(b), false) => b,
         };
-        (data.blocks.len()).checked_sub(1 + computed_order as usize).ok_or_else(
-            || format!(
-                "This order number does not exist. The number you selected was {}, which is greater than the total number of blocks, which is {}", 
-                computed_order,
-                data.blocks.len()).into())
+        Ok(data.resolve_block(block)?)
     }
 
     /// Print `PAGE_SIZE` time blocks in the TUI, corresponding to a certain page number.
@@ -241,19 +630,35 @@ impl PlanCommands {
     ///
     /// * `data` - The user's data
     ///
-    /// * return - The index in the array of time blocks, or an error
-    fn choose_index(data: &mut Data) -> Result<u32, Box<dyn Error>> {
+    /// * return - The id of the chosen time block, or an error
+    fn choose_block(data: &mut Data) -> Result<BlockRef, Box<dyn Error>> {
         let _raw_terminal = RawTerminal::new()?;
         let frame_dur = Duration::from_millis(FRAME_DURATION_MS);
         let total_pages: usize =
             (f64::from(data.blocks.len() as u32) / f64::from(PAGE_SIZE as u32)).ceil() as usize;
 
+        let overlapping = data.overlapping_ids();
+        // Tracked and planned blocks have separate order numbers
+        let (mut tracked, mut planned) = (0, 0);
         let lines: Vec<String> = data
             .blocks
             .iter()
             .rev()
-            .enumerate()
-            .map(|(i, b)| format!("{i}: {b}"))
+            .map(|b| {
+                let order = if b.planned {
+                    planned += 1;
+                    format!("p{}", planned - 1)
+                } else {
+                    tracked += 1;
+                    (tracked - 1).to_string()
+                };
+                let mark = if overlapping.contains(&b.id) {
+                    " (overlaps)"
+                } else {
+                    ""
+                };
+                format!("{order}: #{} {b}{mark}", b.id)
+            })
             .collect();
         let mut page = 0;
         let mut pos: usize = 0;
@@ -271,32 +676,25 @@ impl PlanCommands {
                     (KeyCode::Char('c'), m) if m.contains(KeyModifiers::CONTROL) => {
                         return Err("Interrupt signal".into());
                     }
-                    (KeyCode::Up | KeyCode::Char('k'), _) => {
-                        if pos > 0 {
-                            Self::select_line(page, pos, pos - 1, &lines);
-                            pos -= 1;
-                        }
+                    (KeyCode::Up | KeyCode::Char('k'), _) if pos > 0 => {
+                        Self::select_line(page, pos, pos - 1, &lines);
+                        pos -= 1;
                     }
-                    (KeyCode::Down | KeyCode::Char('j'), _) => {
-                        if pos < max_pos - 1 {
-                            Self::select_line(page, pos, pos + 1, &lines);
-                            pos += 1;
-                        }
+                    (KeyCode::Down | KeyCode::Char('j'), _) if pos < max_pos - 1 => {
+                        Self::select_line(page, pos, pos + 1, &lines);
+                        pos += 1;
                     }
                     (KeyCode::Enter, _) => {
-                        return Ok((pos + (page * (PAGE_SIZE as usize))) as u32);
+                        let index = data.blocks.len() - 1 - (pos + page * (PAGE_SIZE as usize));
+                        return Ok(BlockRef::Id(data.blocks[index].id));
                     }
-                    (KeyCode::Left | KeyCode::Char('h'), _) => {
-                        if page > 0 {
-                            Self::load_page(page - 1, &mut pos, &mut max_pos, total_pages, &lines);
-                            page -= 1;
-                        }
+                    (KeyCode::Left | KeyCode::Char('h'), _) if page > 0 => {
+                        Self::load_page(page - 1, &mut pos, &mut max_pos, total_pages, &lines);
+                        page -= 1;
                     }
-                    (KeyCode::Right | KeyCode::Char('l'), _) => {
-                        if page < total_pages - 1 {
-                            Self::load_page(page + 1, &mut pos, &mut max_pos, total_pages, &lines);
-                            page += 1;
-                        }
+                    (KeyCode::Right | KeyCode::Char('l'), _) if page < total_pages - 1 => {
+                        Self::load_page(page + 1, &mut pos, &mut max_pos, total_pages, &lines);
+                        page += 1;
                     }
                     _ => (),
                 }
@@ -306,53 +704,29 @@ impl PlanCommands {
 
     /// Implementation of the `zyr plan edit` command
     fn exec_edit(
-        category: Option<&String>,
-        from: Option<DateTime<Local>>,
-        duration: Option<Duration>,
-        to: Option<DateTime<Local>>,
-        order_number: Option<u32>,
+        changes: &BlockChanges,
+        block: Option<BlockRef>,
         last: bool,
+        policy: OverlapPolicy,
         data: &mut Data,
     ) -> Result<(), Box<dyn Error>> {
         if data.blocks.is_empty() {
             return Err("You do not have any time blocks stored. Create one with `zyr plan add` or `zyr timer start`".into());
         }
-        if category.is_none() && from.is_none() && duration.is_none() && to.is_none() {
+        if changes.is_empty() {
             return Err("No modifications were provided. Try running zyr plan edit --help to see the intended usage.".into());
         }
 
-        let index = Self::get_index_from_order_number(order_number, last, data)?;
-        let target_block = &mut data.blocks[index];
-
-        let start_unix = match from {
-            Some(dt) => dt.timestamp_millis() as u64,
-            None => target_block.start_unix,
-        };
-        let end_unix = match (duration, to) {
-            (None, None) => target_block.end_unix,
-            (Some(_), Some(_)) => {
-                return Err(
-                "The time block duration and end time cannot both be set at the same time! Please choose only one of them.".into()
-            );
-            }
-            (Some(d), None) => {
-                Some((time_utils::convert(start_unix) + d).timestamp_millis() as u64)
-            }
-            (None, Some(t)) => Some(t.timestamp_millis() as u64),
-        };
-
-        target_block.start_unix = start_unix;
-        target_block.end_unix = end_unix;
-        if let Some(c) = category {
-            target_block.category = c.to_string();
-        }
+        let index = Self::get_index(block, last, data)?;
+        let target_block = changes.apply(&data.blocks[index])?;
+        data.put_block(target_block, policy)?;
 
         Ok(())
     }
 
     /// Implementation of the `zyr plan del` command
     fn exec_del(
-        order_number: Option<u32>,
+        block: Option<BlockRef>,
         last: bool,
         data: &mut Data,
     ) -> Result<(), Box<dyn Error>> {
@@ -360,14 +734,661 @@ impl PlanCommands {
             return Err("You do not have any time blocks stored. Create one with `zyr plan add` or `zyr timer start`".into());
         }
 
-        let index = Self::get_index_from_order_number(order_number, last, data)?;
+        let index = Self::get_index(block, last, data)?;
         if io_utils::confirm("delete this time block") {
-            data.blocks.remove(index);
-            println!("Time block removed successfully");
+            let id = data.blocks[index].id;
+            data.trash_block(index);
+            println!("Time block moved to the trash. Restore it with `zyr trash restore {id}`");
         } else {
             println!("Time block was not removed");
         }
 
         Ok(())
     }
+
+    /// Implementation of the `zyr plan split` command
+    fn exec_split(
+        block: Option<BlockRef>,
+        at: &str,
+        category: Option<&str>,
+        note: Option<&str>,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        if data.blocks.is_empty() {
+            return Err("You do not have any time blocks stored. Create one with `zyr plan add` or `zyr timer start`".into());
+        }
+
+        let index = Self::get_index(block, false, data)?;
+        let first = &data.blocks[index];
+        let day = time_utils::convert(first.start_unix).date_naive();
+        let mut at_unix = parsers::parse_moment(at, day)?.timestamp_millis() as u64;
+        // A time of day before the start refers to the next day for blocks crossing midnight
+        if at_unix <= first.start_unix && parsers::parse_timestamp(at).is_err() {
+            let next_day = day.succ_opt().ok_or("Invalid date")?;
+            at_unix = parsers::parse_moment(at, next_day)?.timestamp_millis() as u64;
+        }
+
+        let now = time_utils::since_unix().as_millis() as u64;
+        if at_unix <= first.start_unix || at_unix >= first.end_or(now) {
+            return Err(format!("{at} is not within the time block {first}").into());
+        }
+
+        let mut second = first.clone();
+        second.id = data.next_block_id();
+        second.start_unix = at_unix;
+        if let Some(c) = category {
+            second.category = c.to_string();
+        }
+        if let Some(n) = note {
+            second.note = Some(n.to_string());
+        }
+
+        data.blocks[index].end_unix = Some(at_unix);
+        println!(
+            "Split into\n  #{} {}",
+            data.blocks[index].id, data.blocks[index]
+        );
+        println!("  #{} {second}", second.id);
+        data.insert_block(second);
+
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan merge` command
+    fn exec_merge(
+        blocks: &[BlockRef],
+        category: Option<&str>,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut ids = vec![];
+        for &block in blocks {
+            let id = data.blocks[data.resolve_block(block)?].id;
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        if ids.len() < 2 {
+            return Err("Select at least two different time blocks to merge".into());
+        }
+
+        let merged = data.merge_blocks(&ids, category, policy)?;
+        println!(
+            "Merged {} time blocks into #{} {merged}",
+            ids.len(),
+            merged.id
+        );
+        Ok(())
+    }
+
+    /// Find the fragments of the same activity that are at most `gap` milliseconds apart.
+    /// Planned and tracked blocks are grouped separately, so a planned block in between does
+    /// not keep tracked fragments from being merged.
+    ///
+    /// * return - The ids of the blocks in each group of at least two fragments
+    fn fragment_groups(blocks: &[TimeBlock], gap: u64) -> Vec<Vec<u64>> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut groups: Vec<Vec<&TimeBlock>> = vec![];
+        for planned in [false, true] {
+            let start = groups.len();
+            // Blocks are sorted, so fragments of the same activity follow each other
+            for block in blocks.iter().filter(|b| b.planned == planned) {
+                match groups[start..].last_mut() {
+                    Some(group)
+                        if group[0].category == block.category
+                            && group.iter().all(|b| b.end_unix.is_some())
+                            && block.start_unix
+                                <= group.iter().map(|b| b.end_or(now)).max().unwrap_or(0) + gap =>
+                    {
+                        group.push(block);
+                    }
+                    _ => groups.push(vec![block]),
+                }
+            }
+        }
+        groups
+            .into_iter()
+            .filter(|g| g.len() > 1)
+            .map(|g| g.iter().map(|b| b.id).collect())
+            .collect()
+    }
+
+    /// Implementation of the `zyr plan merge --auto` command
+    fn exec_merge_auto(
+        gap: Duration,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let groups = Self::fragment_groups(&data.blocks, gap.as_millis() as u64);
+        let fragments: usize = groups.iter().map(Vec::len).sum();
+        if groups.is_empty() {
+            println!("There are no time blocks to merge");
+            return Ok(());
+        }
+        if !io_utils::confirm(&format!(
+            "merge {fragments} time blocks into {}",
+            groups.len()
+        )) {
+            println!("Time blocks were not merged");
+            return Ok(());
+        }
+
+        // Either all groups are merged or none
+        let mut merged = data.clone();
+        for ids in &groups {
+            merged.merge_blocks(ids, None, policy)?;
+        }
+        *data = merged;
+        println!("Merged {fragments} time blocks into {}", groups.len());
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan shift` command
+    fn exec_shift(
+        block: BlockRef,
+        offset: i64,
+        cascade: bool,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let target = &data.blocks[data.resolve_block(block)?];
+        let day = time_utils::convert(target.start_unix);
+        let ids: Vec<u64> = data
+            .blocks
+            .iter()
+            .filter(|b| {
+                b.id == target.id
+                    || cascade
+                        && b.planned == target.planned
+                        && b.start_unix >= target.start_unix
+                        && time_utils::same_day(time_utils::convert(b.start_unix), day)
+            })
+            .map(|b| b.id)
+            .collect();
+
+        data.shift_blocks(&ids, offset, policy)?;
+        let sign = if offset < 0 { "-" } else { "+" };
+        println!(
+            "Moved {} time block{} by {sign}{}",
+            ids.len(),
+            if ids.len() == 1 { "" } else { "s" },
+            time_utils::prettify_duration(Duration::from_millis(offset.unsigned_abs()))
+        );
+        for block in data.blocks.iter().filter(|b| ids.contains(&b.id)) {
+            println!("  #{} {block}", block.id);
+        }
+        Ok(())
+    }
+
+    /// Find the untracked time within the working hours of a day. Time after now is not
+    /// untracked yet.
+    ///
+    /// * `date` - The day
+    /// * `data` - The user's data
+    ///
+    /// * return - The start and end of every gap, or an error if the working hours are invalid
+    fn work_day_gaps(date: NaiveDate, data: &Data) -> Result<Vec<(u64, u64)>, Box<dyn Error>> {
+        let (work_start, work_end) = data.settings.work_hours()?;
+        let now = time_utils::since_unix().as_millis() as u64;
+        let from = time_utils::at_time(date, work_start);
+        let to = time_utils::at_time(date, work_end).min(now);
+        Ok(if from < to {
+            data.gaps(from, to)
+        } else {
+            vec![]
+        })
+    }
+
+    /// Format a gap like `09:00 - 10:30 (1h30m0s)`.
+    fn format_gap((start, end): (u64, u64)) -> String {
+        format!(
+            "{} - {} ({})",
+            time_utils::convert(start).format("%H:%M"),
+            time_utils::convert(end).format("%H:%M"),
+            time_utils::prettify_duration(Duration::from_millis(end - start))
+        )
+    }
+
+    /// Implementation of the `zyr plan gaps` command
+    fn exec_gaps(date: Option<NaiveDate>, data: &Data) -> Result<(), Box<dyn Error>> {
+        let date = date.unwrap_or_else(|| Local::now().date_naive());
+        let gaps = Self::work_day_gaps(date, data)?;
+        let hours = format!(
+            "between {} and {}",
+            data.settings.work_start, data.settings.work_end
+        );
+        if gaps.is_empty() {
+            println!("No untracked time on {date} {hours}");
+            return Ok(());
+        }
+
+        println!("Untracked time on {date} {hours}:");
+        for &gap in &gaps {
+            println!("  {}", Self::format_gap(gap));
+        }
+        let total: u64 = gaps.iter().map(|(start, end)| end - start).sum();
+        println!(
+            "Total: {}. Fill it with `zyr plan fill`",
+            time_utils::prettify_duration(Duration::from_millis(total))
+        );
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan fill` command
+    fn exec_fill(date: Option<NaiveDate>, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        let date = date.unwrap_or_else(|| Local::now().date_naive());
+        let gaps = Self::work_day_gaps(date, data)?;
+        if gaps.is_empty() {
+            println!("No untracked time on {date}");
+            return Ok(());
+        }
+
+        let mut filled = 0;
+        for &(start, end) in &gaps {
+            let Some(category) = io_utils::ask(&format!(
+                "{}\nCategory of this time, or empty or \"ignore\" to skip:",
+                Self::format_gap((start, end))
+            )) else {
+                break;
+            };
+            if category.is_empty() || category == "ignore" {
+                continue;
+            }
+
+            let block = TimeBlock {
+                id: data.next_block_id(),
+                start_unix: start,
+                end_unix: Some(end),
+                category,
+                note: None,
+                tags: vec![],
+                planned: false,
+            };
+            data.put_block(block, OverlapPolicy::Reject)?;
+            filled += 1;
+        }
+        println!("Filled {filled} of {} gaps on {date}", gaps.len());
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan bulk` command
+    fn exec_bulk(
+        selection: &BlockSelection,
+        action: &BulkAction,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        if selection.is_empty() {
+            return Err("Select the time blocks to change with at least one of --category, --tag, --from or --to".into());
+        }
+
+        let ids: Vec<u64> = data
+            .blocks
+            .iter()
+            .filter(|b| selection.matches(b))
+            .map(|b| b.id)
+            .collect();
+        if ids.is_empty() {
+            println!("No time blocks match, nothing to change");
+            return Ok(());
+        }
+
+        for block in data.blocks.iter().filter(|b| ids.contains(&b.id)) {
+            println!("  #{} {block}", block.id);
+        }
+        let blocks = format!(
+            "{} time block{}",
+            ids.len(),
+            if ids.len() == 1 { "" } else { "s" }
+        );
+        let summary = match (&action.set_category, action.shift) {
+            (Some(category), _) => format!("recategorize {blocks} as {category}"),
+            (None, Some(offset)) => format!(
+                "move {blocks} by {}{}",
+                if offset < 0 { "-" } else { "+" },
+                time_utils::prettify_duration(Duration::from_millis(offset.unsigned_abs()))
+            ),
+            (None, None) => format!("delete {blocks}"),
+        };
+        if !io_utils::confirm(&summary) {
+            println!("Time blocks were not changed");
+            return Ok(());
+        }
+
+        if let Some(category) = &action.set_category {
+            for block in data.blocks.iter_mut().filter(|b| ids.contains(&b.id)) {
+                block.category = category.to_string();
+            }
+            println!("Recategorized {blocks} as {category}");
+        } else if let Some(offset) = action.shift {
+            data.shift_blocks(&ids, offset, policy)?;
+            println!("Moved {blocks}");
+        } else {
+            for id in &ids {
+                if let Some(i) = data.block_index(*id) {
+                    data.trash_block(i);
+                }
+            }
+            println!("Moved {blocks} to the trash. Restore them with `zyr trash restore`");
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan copy` command
+    fn exec_copy(
+        block: BlockRef,
+        to_date: NaiveDate,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let block = &data.blocks[data.resolve_block(block)?];
+        let days = (to_date - time_utils::convert(block.start_unix).date_naive()).num_days();
+        for copy in data.copy_blocks(&[block.id], days, policy)? {
+            println!("Copied to #{} {copy}", copy.id);
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan copy-day` command
+    fn exec_copy_day(
+        src: NaiveDate,
+        dst: NaiveDate,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let ids: Vec<u64> = data
+            .blocks
+            .iter()
+            .filter(|b| {
+                b.end_unix.is_some() && time_utils::convert(b.start_unix).date_naive() == src
+            })
+            .map(|b| b.id)
+            .collect();
+        if ids.is_empty() {
+            println!("There are no finished time blocks on {src} to copy");
+            return Ok(());
+        }
+
+        let copies = data.copy_blocks(&ids, (dst - src).num_days(), policy)?;
+        println!("Copied {} time blocks to {dst}", copies.len());
+        for copy in copies {
+            println!("  #{} {copy}", copy.id);
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan agenda` command
+    fn exec_agenda(date: Option<NaiveDate>, data: &Data) {
+        let date = date.unwrap_or_else(|| Local::now().date_naive());
+        let now = time_utils::since_unix().as_millis() as u64;
+        let (from, to) = (
+            time_utils::start_of_day(date),
+            time_utils::start_of_day(date + Days::new(1)),
+        );
+        let blocks: Vec<&TimeBlock> = data
+            .blocks
+            .iter()
+            .filter(|b| b.start_unix < to && b.end_or(now) > from)
+            .collect();
+        if blocks.is_empty() {
+            println!("Nothing was planned or tracked on {date}");
+            return;
+        }
+
+        println!("Schedule of {date}:\n");
+        for block in &blocks {
+            let end = match block.end_unix {
+                Some(end) => time_utils::convert(end).format("%H:%M").to_string(),
+                None => "now".to_string(),
+            };
+            println!(
+                "  {}-{end:<5}  {:<7}  {}{}",
+                time_utils::convert(block.start_unix).format("%H:%M"),
+                if block.planned { "planned" } else { "tracked" },
+                block.category,
+                block
+                    .note
+                    .as_ref()
+                    .map(|n| format!(" ({n})"))
+                    .unwrap_or_default()
+            );
+        }
+
+        let pretty = |millis: u64| time_utils::prettify_duration(Duration::from_millis(millis));
+        println!("\nPlan vs actual:\n");
+        let mut totals: Vec<(&str, (u64, u64))> = Self::agenda_totals(&blocks, from, to, now)
+            .into_iter()
+            .collect();
+        totals.sort_by_key(|(_, (planned, _))| std::cmp::Reverse(*planned));
+        for (category, (planned, tracked)) in &totals {
+            match (tracked * 100).checked_div(*planned) {
+                Some(percent) => println!(
+                    "  {category}: {} planned, {} tracked ({percent}%)",
+                    pretty(*planned),
+                    pretty(*tracked)
+                ),
+                None => println!("  {category}: not planned, {} tracked", pretty(*tracked)),
+            }
+        }
+
+        if let Some(percent) = Self::adherence(&totals) {
+            println!("\nAdherence: {percent}% of the plan");
+        }
+    }
+
+    /// Sum up the planned and tracked time of each category between `from` and `to`.
+    ///
+    /// * return - The planned and the tracked milliseconds by category
+    fn agenda_totals<'a>(
+        blocks: &[&'a TimeBlock],
+        from: u64,
+        to: u64,
+        now: u64,
+    ) -> BTreeMap<&'a str, (u64, u64)> {
+        let mut totals: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for block in blocks {
+            let millis = block
+                .end_or(now)
+                .min(to)
+                .saturating_sub(block.start_unix.max(from));
+            let total = totals.entry(&block.category).or_default();
+            if block.planned {
+                total.0 += millis;
+            } else {
+                total.1 += millis;
+            }
+        }
+        totals
+    }
+
+    /// Calculate how closely the plan was followed, in percent.
+    ///
+    /// * return - The percentage, or `None` if nothing was planned
+    fn adherence(totals: &[(&str, (u64, u64))]) -> Option<u64> {
+        // Tracked time only counts towards the plan up to the planned time of its category
+        let planned: u64 = totals.iter().map(|(_, (p, _))| p).sum();
+        let kept: u64 = totals.iter().map(|(_, (p, t))| (*p).min(*t)).sum();
+        (kept * 100).checked_div(planned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> BlockLabels {
+        BlockLabels {
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    #[test]
+    fn test_exec_add_end_before_start() {
+        let mut data = Data::new();
+        let from = Local::now() - chrono::Duration::hours(2);
+        let to = from - chrono::Duration::hours(1);
+
+        for policy in [OverlapPolicy::Reject, OverlapPolicy::Split] {
+            let result =
+                PlanCommands::exec_add("code", from, None, Some(to), &labels(), policy, &mut data);
+            assert!(result.is_err());
+        }
+        assert!(data.blocks.is_empty());
+    }
+
+    #[test]
+    fn test_exec_add_past_block_order_number() {
+        let mut data = Data::new();
+        let now = Local::now();
+        let hour = chrono::Duration::hours(1);
+        for (category, from) in [("code", now - hour * 2), ("study", now - hour * 5)] {
+            PlanCommands::exec_add(
+                category,
+                from,
+                None,
+                Some(from + hour),
+                &labels(),
+                OverlapPolicy::Reject,
+                &mut data,
+            )
+            .unwrap();
+        }
+
+        let index = data.resolve_block(BlockRef::Order(1)).unwrap();
+        assert_eq!(data.blocks[index].category, "study");
+        assert!(!data.blocks[index].planned);
+    }
+
+    #[test]
+    fn test_exec_split_at_time_of_day() {
+        let day = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+        let at = |time: &str| time_utils::at_time(day, parsers::parse_time(time).unwrap());
+        let mut data = Data::new();
+        data.blocks = vec![TimeBlock {
+            id: 1,
+            start_unix: at("10:00"),
+            end_unix: Some(at("12:00")),
+            category: "code".to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }];
+
+        PlanCommands::exec_split(
+            Some(BlockRef::Id(1)),
+            "11:15",
+            Some("review"),
+            Some("PR"),
+            &mut data,
+        )
+        .unwrap();
+        let spans: Vec<(u64, u64, Option<u64>, &str)> = data
+            .blocks
+            .iter()
+            .map(|b| (b.id, b.start_unix, b.end_unix, &b.category[..]))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, at("10:00"), Some(at("11:15")), "code"),
+                (2, at("11:15"), Some(at("12:00")), "review")
+            ]
+        );
+        assert_eq!(data.blocks[1].note.as_deref(), Some("PR"));
+
+        assert!(
+            PlanCommands::exec_split(Some(BlockRef::Id(1)), "13:00", None, None, &mut data)
+                .is_err()
+        );
+        assert_eq!(data.blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_agenda_totals_and_adherence() {
+        let block = |category: &str, start_unix: u64, end_unix: u64, planned: bool| TimeBlock {
+            id: start_unix,
+            start_unix,
+            end_unix: Some(end_unix),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned,
+        };
+        let blocks = [
+            block("code", 1000, 3000, true),
+            block("code", 1000, 4000, false),
+            block("review", 3000, 4000, true),
+            block("break", 4000, 4500, false),
+        ];
+        let blocks: Vec<&TimeBlock> = blocks.iter().collect();
+
+        let totals = PlanCommands::agenda_totals(&blocks, 0, 10000, 10000);
+        assert_eq!(totals["code"], (2000, 3000));
+        assert_eq!(totals["review"], (1000, 0));
+        assert_eq!(totals["break"], (0, 500));
+
+        // Tracking more code than planned does not make up for the missed review
+        let totals: Vec<(&str, (u64, u64))> = totals.into_iter().collect();
+        assert_eq!(PlanCommands::adherence(&totals), Some(66));
+        assert_eq!(PlanCommands::adherence(&totals[..1]), None);
+    }
+
+    #[test]
+    fn test_exec_shift_cascade_keeps_other_kind() {
+        let day = NaiveDate::from_ymd_opt(2024, 5, 6).unwrap();
+        let at = |time: &str| time_utils::at_time(day, parsers::parse_time(time).unwrap());
+        let block = |id: u64, start: &str, end: &str, planned: bool| TimeBlock {
+            id,
+            start_unix: at(start),
+            end_unix: Some(at(end)),
+            category: "code".to_string(),
+            note: None,
+            tags: vec![],
+            planned,
+        };
+        let mut data = Data::new();
+        data.blocks = vec![
+            block(1, "09:00", "10:00", false),
+            block(2, "10:00", "11:00", true),
+            block(3, "11:00", "12:00", false),
+        ];
+
+        let minutes = 30 * 60 * 1000;
+        PlanCommands::exec_shift(
+            BlockRef::Id(1),
+            minutes,
+            true,
+            OverlapPolicy::Reject,
+            &mut data,
+        )
+        .unwrap();
+        let starts: Vec<(u64, u64)> = data.blocks.iter().map(|b| (b.id, b.start_unix)).collect();
+        assert_eq!(
+            starts,
+            vec![(1, at("09:30")), (2, at("10:00")), (3, at("11:30"))]
+        );
+    }
+
+    #[test]
+    fn test_fragment_groups_skip_planned_blocks_in_between() {
+        let block = |id: u64, category: &str, start_unix: u64, planned: bool| TimeBlock {
+            id,
+            start_unix,
+            end_unix: Some(start_unix + 100),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned,
+        };
+        let blocks = vec![
+            block(1, "code", 0, false),
+            block(2, "standup", 120, true),
+            block(3, "code", 150, false),
+            block(4, "code", 1000, false),
+        ];
+
+        assert_eq!(PlanCommands::fragment_groups(&blocks, 60), vec![vec![1, 3]]);
+    }
 }
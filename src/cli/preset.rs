@@ -0,0 +1,171 @@
+use crate::{
+    domain::{Data, Executable, Preset},
+    storage::Store,
+    utils::parsers,
+};
+use clap::Subcommand;
+use std::error::Error;
+use std::time::Duration;
+
+#[derive(Subcommand, PartialEq)]
+pub enum PresetCommands {
+    /// Save a named timer, or change an existing one.
+    /// E.g., zyr timer preset add focus code --duration 90m
+    Add {
+        /// The name to start the timer with, e.g., focus for zyr timer start @focus
+        name: String,
+
+        /// The type of work or activity. E.g., code, study, break
+        category: String,
+
+        /// The duration of the timer, which then counts backwards. E.g., 90m
+        #[arg(short, long, value_parser = parsers::parse_duration)]
+        duration: Option<Duration>,
+
+        /// The note of the time blocks started with the preset
+        #[arg(short, long)]
+        note: Option<String>,
+
+        /// A tag of the time blocks started with the preset. Can be repeated.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Show all presets
+    List,
+    /// Delete presets. E.g., zyr timer preset remove focus
+    Remove {
+        /// The names of the presets, as shown by `zyr timer preset list`
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+}
+
+impl Executable for PresetCommands {
+    fn execute(&self, data: &mut Data, _store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Add {
+                name,
+                category,
+                duration,
+                note,
+                tags,
+            } => {
+                let preset = Preset {
+                    name: name.trim_start_matches('@').to_string(),
+                    category: category.to_string(),
+                    duration_ms: duration.map(|d| d.as_millis() as u64),
+                    note: note.clone(),
+                    tags: tags.clone(),
+                };
+                Self::exec_add(preset, data)?
+            }
+            Self::List => Self::exec_list(data),
+            Self::Remove { names } => Self::exec_remove(names, data)?,
+        }
+        Ok(())
+    }
+}
+
+impl PresetCommands {
+    /// Implementation of the `zyr timer preset add` command
+    fn exec_add(preset: Preset, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        if preset.name.is_empty() || preset.name.contains(char::is_whitespace) {
+            return Err("Preset names cannot be empty or contain spaces".into());
+        }
+
+        match data.presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(existing) => {
+                println!("Changed the preset {preset}");
+                *existing = preset;
+            }
+            None => {
+                println!("Added the preset {preset}");
+                data.presets.push(preset);
+            }
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr timer preset list` command
+    fn exec_list(data: &Data) {
+        if data.presets.is_empty() {
+            println!("There are no presets. Add one with `zyr timer preset add`");
+            return;
+        }
+        for preset in &data.presets {
+            println!("{preset}");
+        }
+    }
+
+    /// Implementation of the `zyr timer preset remove` command
+    fn exec_remove(names: &[String], data: &mut Data) -> Result<(), Box<dyn Error>> {
+        let names: Vec<&str> = names.iter().map(|n| n.trim_start_matches('@')).collect();
+        if let Some(name) = names
+            .iter()
+            .find(|&&name| !data.presets.iter().any(|p| p.name == name))
+        {
+            return Err(format!(
+                "There is no preset named {name}. See `zyr timer preset list` for all presets."
+            )
+            .into());
+        }
+
+        data.presets.retain(|p| !names.contains(&p.name.as_str()));
+        println!("Removed {} preset(s)", names.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn add(name: &str, category: &str) -> PresetCommands {
+        PresetCommands::Add {
+            name: name.to_string(),
+            category: category.to_string(),
+            duration: Some(Duration::from_secs(300)),
+            note: None,
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn test_add_and_remove_strip_at() {
+        let mut store = Store::new(PathBuf::from("unused.json"));
+        let mut data = Data::new();
+
+        add("@tea", "break").execute(&mut data, &mut store).unwrap();
+        add("tea", "snack").execute(&mut data, &mut store).unwrap();
+        assert_eq!(data.presets.len(), 1);
+        assert_eq!(data.presets[0].name, "tea");
+        assert_eq!(data.presets[0].category, "snack");
+
+        let remove = |names: &[&str]| PresetCommands::Remove {
+            names: names.iter().map(|n| n.to_string()).collect(),
+        };
+        assert!(
+            remove(&["@tea", "focus"])
+                .execute(&mut data, &mut store)
+                .is_err()
+        );
+        assert_eq!(data.presets.len(), 1);
+        remove(&["@tea"]).execute(&mut data, &mut store).unwrap();
+        assert!(data.presets.is_empty());
+    }
+
+    #[test]
+    fn test_add_rejects_invalid_names() {
+        let mut store = Store::new(PathBuf::from("unused.json"));
+        let mut data = Data::new();
+
+        assert!(add("@", "break").execute(&mut data, &mut store).is_err());
+        assert!(
+            add("a tea", "break")
+                .execute(&mut data, &mut store)
+                .is_err()
+        );
+        assert!(data.presets.is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    domain::{Data, Executable},
+    storage::Store,
+    utils::file_utils,
+};
+use clap::Subcommand;
+use std::error::Error;
+
+#[derive(Subcommand, PartialEq)]
+pub enum ProfileCommands {
+    /// List all profiles. The active profile is marked with an asterisk.
+    List,
+    /// Create a new profile with its own data file
+    Create {
+        /// The name of the profile. E.g., work, personal
+        name: String,
+    },
+    /// Make a profile the default for all future invocations
+    Switch {
+        /// The name of the profile to switch to
+        name: String,
+    },
+}
+
+impl Executable for ProfileCommands {
+    fn execute(&self, _data: &mut Data, _store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::List => Self::exec_list(),
+            Self::Create { name } => Self::exec_create(name)?,
+            Self::Switch { name } => Self::exec_switch(name)?,
+        }
+        Ok(())
+    }
+}
+
+impl ProfileCommands {
+    /// Implementation of the `zyr profile list` command
+    fn exec_list() {
+        let active = file_utils::get_active_profile();
+        for name in file_utils::list_profiles() {
+            let marker = if name == active { '*' } else { ' ' };
+            println!(
+                "{marker} {name} ({})",
+                file_utils::get_profile_path(&name).display()
+            );
+        }
+    }
+
+    /// Implementation of the `zyr profile create` command
+    fn exec_create(name: &str) -> Result<(), Box<dyn Error>> {
+        file_utils::validate_profile_name(name)?;
+        let path = file_utils::get_profile_path(name);
+        if path.exists() {
+            return Err(format!("Profile \"{name}\" already exists").into());
+        }
+
+        file_utils::create_if_missing(&path)?;
+        println!("Profile \"{name}\" created at {}", path.display());
+        Ok(())
+    }
+
+    /// Implementation of the `zyr profile switch` command
+    fn exec_switch(name: &str) -> Result<(), Box<dyn Error>> {
+        file_utils::validate_profile_name(name)?;
+        if name != file_utils::DEFAULT_PROFILE && !file_utils::get_profile_path(name).exists() {
+            return Err(format!(
+                "Profile \"{name}\" does not exist. Create it with `zyr profile create {name}`"
+            )
+            .into());
+        }
+
+        file_utils::set_active_profile(name)?;
+        println!("Switched to profile \"{name}\"");
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    domain::{Data, Executable, Recurrence},
+    storage::Store,
+    utils::{parsers, time_utils},
+};
+use chrono::{Local, NaiveDate, NaiveTime};
+use clap::Subcommand;
+use std::error::Error;
+use std::time::Duration;
+
+#[derive(Subcommand, PartialEq)]
+pub enum RecurCommands {
+    /// Repeat a time block on certain days.
+    /// E.g., zyr plan recur add standup --days weekdays --start 09:30 --duration 15m
+    Add {
+        /// The type of work or activity. E.g., code, study, break
+        category: String,
+
+        /// The days to repeat the block on: daily, weekdays, weekends or e.g. mon,wed,fri
+        #[arg(long, default_value = "daily")]
+        days: String,
+
+        /// The time of day the block starts at, e.g., 09:30
+        #[arg(short, long, value_parser = parsers::parse_time)]
+        start: NaiveTime,
+
+        /// The duration of the block, e.g., 15m.
+        /// Either specify a duration or an end time.
+        #[arg(short, long, value_parser = parsers::parse_duration)]
+        duration: Option<Duration>,
+
+        /// The time of day the block ends at, e.g., 13:00.
+        /// Either specify a duration or an end time.
+        #[arg(short, long, value_parser = parsers::parse_time)]
+        end: Option<NaiveTime>,
+
+        /// A description of the block
+        #[arg(short, long)]
+        note: Option<String>,
+
+        /// A label that groups blocks across categories, e.g., a project. Can be repeated.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Show the recurring time blocks and their ids
+    List,
+    /// Stop repeating time blocks. Blocks that were already created are kept.
+    Remove {
+        /// The ids of the recurring blocks, as shown by `zyr plan recur list`
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+    /// Create the time blocks of all recurring blocks up to a day.
+    /// This happens for the current day whenever zyr runs.
+    Apply {
+        /// The last day to create time blocks for, e.g., friday. Defaults to today.
+        #[arg(long, value_parser = parsers::parse_date)]
+        until: Option<NaiveDate>,
+    },
+}
+
+impl Executable for RecurCommands {
+    fn execute(&self, data: &mut Data, _store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Add {
+                category,
+                days,
+                start,
+                duration,
+                end,
+                note,
+                tags,
+            } => {
+                let recurrence = Recurrence {
+                    id: data.recurrences.iter().map(|r| r.id).max().unwrap_or(0) + 1,
+                    category: category.to_string(),
+                    weekdays: parsers::parse_weekdays(days)?,
+                    start: start.format("%H:%M").to_string(),
+                    end: Self::end_time(*start, *duration, *end)?
+                        .format("%H:%M")
+                        .to_string(),
+                    note: note.clone(),
+                    tags: tags.clone(),
+                    applied_until: time_utils::start_of_day(Local::now().date_naive()),
+                };
+                Self::exec_add(recurrence, data)
+            }
+            Self::List => Self::exec_list(data),
+            Self::Remove { ids } => Self::exec_remove(ids, data)?,
+            Self::Apply { until } => Self::exec_apply(*until, data),
+        }
+        Ok(())
+    }
+}
+
+impl RecurCommands {
+    /// Calculate when a recurring block ends on the day it starts.
+    ///
+    /// * return - The end time, or an error if it is missing, ambiguous or on the next day
+    fn end_time(
+        start: NaiveTime,
+        duration: Option<Duration>,
+        end: Option<NaiveTime>,
+    ) -> Result<NaiveTime, Box<dyn Error>> {
+        let end = match (duration, end) {
+            (None, None) => {
+                return Err("Either the time block duration or end time must be set!".into());
+            }
+            (Some(_), Some(_)) => {
+                return Err(
+                "The time block duration and end time cannot both be set at the same time! Please choose only one of them.".into()
+            );
+            }
+            (Some(d), None) => start + d,
+            (None, Some(e)) => e,
+        };
+        if end <= start {
+            return Err("Recurring time blocks have to end on the day they start".into());
+        }
+        Ok(end)
+    }
+
+    /// Implementation of the `zyr plan recur add` command
+    fn exec_add(recurrence: Recurrence, data: &mut Data) {
+        println!("Added recurring block {} {recurrence}", recurrence.id);
+        data.recurrences.push(recurrence);
+        let (created, _) = data.apply_recurrences(Local::now().date_naive());
+        if created > 0 {
+            println!("Created today's time block");
+        }
+    }
+
+    /// Implementation of the `zyr plan recur list` command
+    fn exec_list(data: &Data) {
+        if data.recurrences.is_empty() {
+            println!("There are no recurring time blocks. Add one with `zyr plan recur add`");
+            return;
+        }
+        for recurrence in &data.recurrences {
+            println!("{:>5}  {recurrence}", recurrence.id);
+        }
+    }
+
+    /// Implementation of the `zyr plan recur remove` command
+    fn exec_remove(ids: &[u64], data: &mut Data) -> Result<(), Box<dyn Error>> {
+        if let Some(id) = ids
+            .iter()
+            .find(|&&id| !data.recurrences.iter().any(|r| r.id == id))
+        {
+            return Err(format!(
+                "There is no recurring time block with id {id}. See `zyr plan recur list` for all of them."
+            )
+            .into());
+        }
+
+        data.recurrences.retain(|r| !ids.contains(&r.id));
+        println!(
+            "Removed {} recurring time block(s). Time blocks that were already created are kept.",
+            ids.len()
+        );
+        Ok(())
+    }
+
+    /// Implementation of the `zyr plan recur apply` command
+    fn exec_apply(until: Option<NaiveDate>, data: &mut Data) {
+        let until = until.unwrap_or_else(|| Local::now().date_naive());
+        let (created, skipped) = data.apply_recurrences(until);
+        println!("Created {created} time block(s) up to {until}");
+        if skipped > 0 {
+            println!("Skipped {skipped} time block(s) that would overlap existing ones");
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::{
+    domain::{Data, Executable},
+    storage::{StorageKind, Store},
+};
+use clap::Subcommand;
+use std::error::Error;
+
+#[derive(Subcommand, PartialEq)]
+pub enum StorageCommands {
+    /// Move the data to another storage format. E.g., zyr storage migrate --to sqlite
+    Migrate {
+        /// The format to store the data in from now on
+        #[arg(long, value_enum)]
+        to: StorageKind,
+    },
+    /// Replace the change journal with a snapshot of the data. The old changes are archived.
+    Compact,
+}
+
+impl Executable for StorageCommands {
+    fn execute(&self, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Migrate { to } => Self::exec_migrate(*to, data, store)?,
+            Self::Compact => Self::exec_compact(data, store)?,
+        }
+        Ok(())
+    }
+}
+
+impl StorageCommands {
+    /// Implementation of the `zyr storage migrate` command
+    fn exec_migrate(to: StorageKind, data: &Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+        let old_path = store.path().to_path_buf();
+        let new_path = store.migrate_to(to, data)?;
+        println!(
+            "Data moved from {} to {}",
+            old_path.display(),
+            new_path.display()
+        );
+        println!(
+            "Profiles pick up the new file automatically. If you use --data or ZYR_DATA, point them to the new file."
+        );
+        Ok(())
+    }
+
+    /// Implementation of the `zyr storage compact` command
+    fn exec_compact(data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+        if store.compact(data)? {
+            println!("Journal compacted");
+        } else {
+            println!("Only the journal storage format can be compacted");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use super::plan::OverlapArgs;
+use crate::{
+    domain::{Data, Executable, OverlapPolicy, Template, TimeBlock},
+    storage::Store,
+    utils::{io_utils, parsers, time_utils},
+};
+use chrono::{Local, NaiveDate};
+use clap::{ArgAction, Subcommand};
+use std::error::Error;
+
+#[derive(Subcommand, PartialEq)]
+pub enum TemplateCommands {
+    /// Save the time blocks of a day as a template. E.g., zyr template save deepwork-day
+    Save {
+        /// The name of the template
+        name: String,
+
+        /// The day to save, e.g., 2010-12-31 or yesterday. Defaults to today.
+        #[arg(long, value_parser = parsers::parse_date)]
+        date: Option<NaiveDate>,
+
+        /// Save the planned blocks of the day instead of the tracked ones
+        #[arg(long, action = ArgAction::SetTrue)]
+        planned: bool,
+    },
+    /// Plan a day with the blocks of a template.
+    /// E.g., zyr template apply deepwork-day --date tomorrow
+    Apply {
+        /// The name of the template, as shown by `zyr template list`
+        name: String,
+
+        /// The day to plan, e.g., 2010-12-31 or tomorrow. Defaults to today.
+        #[arg(long, value_parser = parsers::parse_date)]
+        date: Option<NaiveDate>,
+
+        #[command(flatten)]
+        overlap: OverlapArgs,
+    },
+    /// Show all templates and their time blocks
+    List,
+    /// Delete a template. Blocks that were planned with it are kept.
+    Delete {
+        /// The name of the template, as shown by `zyr template list`
+        name: String,
+    },
+}
+
+impl Executable for TemplateCommands {
+    fn execute(&self, data: &mut Data, _store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Save {
+                name,
+                date,
+                planned,
+            } => Self::exec_save(name, *date, *planned, data)?,
+            Self::Apply {
+                name,
+                date,
+                overlap,
+            } => Self::exec_apply(name, *date, overlap.policy(), data)?,
+            Self::List => Self::exec_list(data),
+            Self::Delete { name } => Self::exec_delete(name, data)?,
+        }
+        Ok(())
+    }
+}
+
+impl TemplateCommands {
+    /// Find the position of the template with the given name.
+    fn template_index(name: &str, data: &Data) -> Result<usize, Box<dyn Error>> {
+        data.templates
+            .iter()
+            .position(|t| t.name == name)
+            .ok_or_else(|| {
+                format!(
+                    "There is no template named {name}. See `zyr template list` for all templates."
+                )
+                .into()
+            })
+    }
+
+    /// Implementation of the `zyr template save` command
+    fn exec_save(
+        name: &str,
+        date: Option<NaiveDate>,
+        planned: bool,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let date = date.unwrap_or_else(|| Local::now().date_naive());
+        let blocks: Vec<&TimeBlock> = data
+            .blocks
+            .iter()
+            .filter(|b| {
+                b.planned == planned
+                    && b.end_unix.is_some()
+                    && time_utils::convert(b.start_unix).date_naive() == date
+            })
+            .collect();
+        if blocks.is_empty() {
+            return Err(format!(
+                "There are no finished {} time blocks on {date} to save",
+                if planned { "planned" } else { "tracked" }
+            )
+            .into());
+        }
+
+        let template = Template::from_blocks(name, &blocks);
+        let existing = data.templates.iter().position(|t| t.name == name);
+        if let Some(i) = existing {
+            if !io_utils::confirm(&format!("replace the template {name}")) {
+                println!("The template was not saved");
+                return Ok(());
+            }
+            data.templates[i] = template;
+        } else {
+            data.templates.push(template);
+        }
+        println!(
+            "Saved {} time blocks of {date} as the template {name}",
+            blocks.len()
+        );
+        Ok(())
+    }
+
+    /// Implementation of the `zyr template apply` command
+    fn exec_apply(
+        name: &str,
+        date: Option<NaiveDate>,
+        policy: OverlapPolicy,
+        data: &mut Data,
+    ) -> Result<(), Box<dyn Error>> {
+        let date = date.unwrap_or_else(|| Local::now().date_naive());
+        let template = &data.templates[Self::template_index(name, data)?];
+        let blocks = template.blocks_on(date)?;
+
+        // Either all blocks are planned or none
+        let mut planned = data.clone();
+        for block in blocks {
+            let block = TimeBlock {
+                id: planned.next_block_id(),
+                ..block
+            };
+            planned.put_block(block, policy)?;
+        }
+        println!(
+            "Planned {} time blocks on {date} with the template {name}",
+            template.blocks.len()
+        );
+        *data = planned;
+        Ok(())
+    }
+
+    /// Implementation of the `zyr template list` command
+    fn exec_list(data: &Data) {
+        if data.templates.is_empty() {
+            println!("There are no templates. Save a day as one with `zyr template save`");
+            return;
+        }
+
+        for template in &data.templates {
+            println!("{}:", template.name);
+            for block in &template.blocks {
+                println!("  {}-{}  {}", block.start, block.end, block.category);
+            }
+        }
+    }
+
+    /// Implementation of the `zyr template delete` command
+    fn exec_delete(name: &str, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        let index = Self::template_index(name, data)?;
+        data.templates.remove(index);
+        println!("Deleted the template {name}");
+        Ok(())
+    }
+}
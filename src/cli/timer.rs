@@ -1,13 +1,16 @@
+use super::preset::PresetCommands;
 use crate::{
-    domain::{Data, Executable, Timer},
+    domain::{Data, Executable, QueuedTimer, Timer},
+    storage::Store,
     terminal::{FRAME_DURATION_MS, RawTerminal},
-    utils::{file_utils, parsers},
+    utils::parsers,
 };
 use clap::{ArgAction, Subcommand};
 use crossterm::{
     cursor,
     event::{self, Event, KeyCode, KeyModifiers},
     execute, style,
+    terminal::{self, ClearType},
 };
 use std::error::Error;
 use std::io;
@@ -15,9 +18,10 @@ use std::time::Duration;
 
 #[derive(Subcommand, PartialEq)]
 pub enum TimerCommands {
-    /// Start a new timer
+    /// Start a new timer. E.g., zyr timer start code or zyr timer start @focus
     Start {
-        /// The type of work or activity. E.g., code, study, break
+        /// The type of work or activity. E.g., code, study, break.
+        /// A preset name with a leading @, e.g., @focus, starts the timer of that preset.
         category: String,
 
         /// Optional duration of the timer. If provided, the timer will count backwards.
@@ -27,6 +31,19 @@ pub enum TimerCommands {
         /// Run the `zyr timer show` command immediately after
         #[arg(short, long, action = ArgAction::SetTrue)]
         show: bool,
+
+        /// A label that groups blocks across categories, e.g., a project. Can be repeated.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
+        /// A description of what is being done
+        #[arg(short, long)]
+        note: Option<String>,
+    },
+    /// Save, list, or remove named timers that are started with zyr timer start @name
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommands,
     },
     /// Make the timer end later. E.g., zyr timer add 5m
     Add {
@@ -40,27 +57,56 @@ pub enum TimerCommands {
         #[arg(value_parser = parsers::parse_duration)]
         duration: Duration,
     },
-    /// Stop the currently running timer
+    /// Run countdown timers back-to-back, each recorded as its own time block.
+    /// E.g., zyr timer queue code:50m break:10m code:50m
+    Queue {
+        /// The timers as category:duration, e.g., code:50m
+        #[arg(value_parser = parsers::parse_queue_step, required_unless_present = "clear")]
+        steps: Vec<(String, Duration)>,
+
+        /// Remove the timers that have not started yet. The running timer keeps running.
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "steps")]
+        clear: bool,
+
+        /// Run the `zyr timer show` command immediately after
+        #[arg(short, long, action = ArgAction::SetTrue)]
+        show: bool,
+    },
+    /// Stop the currently running timer. The next queued timer starts right away.
     End,
     /// Show the currently running timer
     Show,
 }
 
 impl Executable for TimerCommands {
-    fn execute(&self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+    fn execute(&self, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
         match self {
             Self::Start {
                 category,
                 duration,
                 show,
-            } => Self::exec_start(category, *duration, *show, data)?,
+                tags,
+                note,
+            } => Self::exec_start(
+                category,
+                *duration,
+                *show,
+                tags,
+                note.as_deref(),
+                data,
+                store,
+            )?,
+            Self::Preset { command } => command.execute(data, store)?,
             Self::Add { duration } => Self::exec_add(*duration, data)?,
             Self::Sub { duration } => Self::exec_sub(*duration, data)?,
+            Self::Queue { steps, clear, show } => {
+                Self::exec_queue(steps, *clear, *show, data, store)?
+            }
             Self::End => {
                 Self::exec_end(data);
             }
             Self::Show => {
-                Self::exec_show(data)?;
+                Self::exec_show(data, store)?;
             }
         }
         Ok(())
@@ -73,12 +119,39 @@ impl TimerCommands {
         category: &str,
         duration: Option<Duration>,
         show: bool,
+        tags: &[String],
+        note: Option<&str>,
         data: &mut Data,
+        store: &mut Store,
     ) -> Result<(), Box<dyn Error>> {
         if data.get_running_timer().is_some() {
             return Err("Timer already started!".into());
         }
 
+        // Arguments that are given explicitly override the ones of the preset
+        let mut tags = tags.to_vec();
+        let mut note = note.map(str::to_string);
+        let (category, duration) = match category.strip_prefix('@') {
+            Some(name) => {
+                let preset = data.presets.iter().find(|p| p.name == name).ok_or_else(|| {
+                    format!(
+                        "There is no preset named {name}. See `zyr timer preset list` for all presets."
+                    )
+                })?;
+                for tag in preset.tags.iter().rev() {
+                    if !tags.contains(tag) {
+                        tags.insert(0, tag.to_string());
+                    }
+                }
+                note = note.or_else(|| preset.note.clone());
+                (
+                    preset.category.clone(),
+                    duration.or(preset.duration_ms.map(Duration::from_millis)),
+                )
+            }
+            None => (category.to_string(), duration),
+        };
+
         let timer: Timer;
 
         if let Some(d) = duration {
@@ -87,25 +160,74 @@ impl TimerCommands {
             timer = Timer::new();
         }
 
-        data.blocks.push(timer.to_block(category));
-        data.save(&file_utils::get_data_path());
+        let mut block = timer.to_block(data.next_block_id(), &category);
+        block.tags = tags;
+        block.note = note;
+        data.insert_block(block);
+        store.commit(data)?;
+
+        if show {
+            Self::exec_show(data, store)?;
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr timer queue` command
+    fn exec_queue(
+        steps: &[(String, Duration)],
+        clear: bool,
+        show: bool,
+        data: &mut Data,
+        store: &mut Store,
+    ) -> Result<(), Box<dyn Error>> {
+        if clear {
+            println!("Removed {} queued timer(s)", data.queue.waiting.len());
+            data.queue.waiting.clear();
+            return Ok(());
+        }
+
+        let steps = steps.iter().map(|(category, duration)| QueuedTimer {
+            category: category.to_string(),
+            duration_ms: duration.as_millis() as u64,
+        });
+        match data.running_block_index() {
+            Some(i) if data.blocks[i].end_unix.is_none() => {
+                return Err("The running timer has no end. Stop it with `zyr timer end` before queueing timers.".into());
+            }
+            Some(i) => {
+                data.queue.current = Some(data.blocks[i].id);
+                data.queue.waiting.extend(steps);
+                println!(
+                    "{} timer(s) queued after the running one",
+                    data.queue.waiting.len()
+                );
+            }
+            None => {
+                data.queue.current = None;
+                data.queue.waiting = steps.collect();
+                for block in data.advance_queue() {
+                    println!("Started {}", block.category);
+                }
+                println!("{} timer(s) queued", data.queue.waiting.len());
+            }
+        }
+        store.commit(data)?;
 
         if show {
-            Self::exec_show(data)?;
+            Self::exec_show(data, store)?;
         }
         Ok(())
     }
 
     /// Implementation of the `zyr timer add` command
     fn exec_add(duration: Duration, data: &mut Data) -> Result<(), Box<dyn Error>> {
-        if let Some(mut timer) = data.get_running_timer() {
+        if let Some(index) = data.running_block_index() {
+            let mut timer = Timer::from_block(&data.blocks[index]);
             if timer.end_unix.is_none() {
                 return Err("Timer does not have a set end time".into());
             }
             timer.add(duration);
-            let category = data.blocks[data.blocks.len() - 1].category.clone();
-            data.blocks.pop();
-            data.blocks.push(timer.to_block(&category));
+            data.blocks[index].end_unix = timer.end_unix;
         } else {
             println!("No timer is running");
         }
@@ -114,14 +236,13 @@ impl TimerCommands {
 
     /// Implementation of the `zyr timer sub` command
     fn exec_sub(duration: Duration, data: &mut Data) -> Result<(), Box<dyn Error>> {
-        if let Some(mut timer) = data.get_running_timer() {
+        if let Some(index) = data.running_block_index() {
+            let mut timer = Timer::from_block(&data.blocks[index]);
             if timer.end_unix.is_none() {
                 return Err("Timer does not have a set end time".into());
             }
             timer.sub(duration);
-            let category = data.blocks[data.blocks.len() - 1].category.clone();
-            data.blocks.pop();
-            data.blocks.push(timer.to_block(&category));
+            data.blocks[index].end_unix = timer.end_unix;
         } else {
             println!("No timer is running");
         }
@@ -130,52 +251,155 @@ impl TimerCommands {
 
     /// Implementation of the `zyr timer end` command
     fn exec_end(data: &mut Data) {
-        if let Some(mut timer) = data.get_running_timer() {
+        if let Some(index) = data.running_block_index() {
+            let mut timer = Timer::from_block(&data.blocks[index]);
             timer.end();
-            let category = data.blocks[data.blocks.len() - 1].category.clone();
-            data.blocks.pop();
-            data.blocks.push(timer.to_block(&category));
+            data.blocks[index].end_unix = timer.end_unix;
             println!("Timer stopped successfully");
+            for block in data.advance_queue() {
+                println!("Started the queued timer {}", block.category);
+            }
         } else {
             println!("No timer to end");
         }
     }
 
     /// Implementation of the `zyr timer show` command
-    fn exec_show(data: &Data) -> Result<(), Box<dyn Error>> {
-        if let Some(timer) = data.get_running_timer() {
-            fn print_timer(timer: &Timer) {
-                execute!(
-                    io::stdout(),
-                    cursor::MoveToColumn(0),
-                    style::Print(format!("{timer}"))
-                )
-                .unwrap();
-            }
+    fn exec_show(data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>> {
+        let Some(mut timer) = data.get_running_timer() else {
+            println!("No timer is running");
+            return Ok(());
+        };
 
-            let _raw_terminal = RawTerminal::new()?;
-            let mut dur = Duration::ZERO;
-            let frame_dur = Duration::from_millis(FRAME_DURATION_MS);
-
-            print_timer(&timer);
-            loop {
-                if event::poll(frame_dur)?
-                    && let Event::Key(e) = event::read()?
-                    && e.code == KeyCode::Char('c')
-                    && e.modifiers.contains(KeyModifiers::CONTROL)
-                {
-                    break;
-                }
-                dur += frame_dur;
-                if dur >= Duration::from_secs(1) {
-                    print_timer(&timer);
-                    dur = Duration::ZERO;
+        fn print_timer(timer: &Timer, data: &Data, status: &str) {
+            let next_up = match data.queue.waiting.as_slice() {
+                [] => String::new(),
+                [next] => format!("Next up: {next}"),
+                [next, rest @ ..] => format!("Next up: {next}, then {} more", rest.len()),
+            };
+            execute!(
+                io::stdout(),
+                cursor::MoveTo(0, 0),
+                terminal::Clear(ClearType::CurrentLine),
+                style::Print(format!("{timer}")),
+                cursor::MoveTo(0, 1),
+                terminal::Clear(ClearType::CurrentLine),
+                style::Print(next_up),
+                cursor::MoveTo(0, 2),
+                terminal::Clear(ClearType::CurrentLine),
+                style::Print(status),
+            )
+            .unwrap();
+        }
+
+        let _raw_terminal = RawTerminal::new()?;
+        let mut dur = Duration::ZERO;
+        let frame_dur = Duration::from_millis(FRAME_DURATION_MS);
+        let mut status = String::new();
+
+        print_timer(&timer, data, &status);
+        loop {
+            if event::poll(frame_dur)?
+                && let Event::Key(e) = event::read()?
+                && e.code == KeyCode::Char('c')
+                && e.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                break;
+            }
+            dur += frame_dur;
+            if dur >= Duration::from_secs(1) {
+                // Move on to the next queued timer once the countdown has finished
+                if let Some(block) = data.advance_queue().pop() {
+                    store.commit(data)?;
+                    timer = Timer::from_block(&block);
+                    status = format!("Time for {}", block.category);
+                    // Ring the terminal bell once as a notification
+                    execute!(io::stdout(), style::Print('\u{7}'))?;
                 }
+                print_timer(&timer, data, &status);
+                dur = Duration::ZERO;
             }
-        } else {
-            println!("No timer is running");
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Preset;
+
+    fn temp_store(name: &str) -> Store {
+        let dir = std::env::temp_dir().join(format!("zyr-timer-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Store::new(dir.join("data.json"))
+    }
+
+    fn focus() -> Preset {
+        Preset {
+            name: "focus".to_string(),
+            category: "code".to_string(),
+            duration_ms: Some(90 * 60 * 1000),
+            note: Some("deep work".to_string()),
+            tags: vec!["deep".to_string(), "website".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_exec_start_preset() {
+        let mut store = temp_store("preset");
+        let mut data = store.load().unwrap();
+        data.presets.push(focus());
+        let tags = vec!["client".to_string(), "deep".to_string()];
+
+        TimerCommands::exec_start("@focus", None, false, &tags, None, &mut data, &mut store)
+            .unwrap();
+        let block = &data.blocks[0];
+        assert_eq!(block.category, "code");
+        assert_eq!(block.end_unix, Some(block.start_unix + 90 * 60 * 1000));
+        assert_eq!(block.note.as_deref(), Some("deep work"));
+        assert_eq!(block.tags, vec!["website", "client", "deep"]);
+        std::fs::remove_dir_all(store.path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_exec_start_preset_overrides() {
+        let mut store = temp_store("override");
+        let mut data = store.load().unwrap();
+        data.presets.push(focus());
+        let duration = Some(Duration::from_secs(60));
+
+        TimerCommands::exec_start(
+            "@focus",
+            duration,
+            false,
+            &[],
+            Some("PR"),
+            &mut data,
+            &mut store,
+        )
+        .unwrap();
+        let block = &data.blocks[0];
+        assert_eq!(block.end_unix, Some(block.start_unix + 60 * 1000));
+        assert_eq!(block.note.as_deref(), Some("PR"));
+        std::fs::remove_dir_all(store.path().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_exec_start_unknown_preset() {
+        let mut store = temp_store("unknown");
+        let mut data = store.load().unwrap();
+
+        let result =
+            TimerCommands::exec_start("@tea", None, false, &[], None, &mut data, &mut store);
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("no preset named tea")
+        );
+        assert!(data.blocks.is_empty());
+        std::fs::remove_dir_all(store.path().parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    domain::{Data, Executable},
+    storage::Store,
+    utils::{io_utils, time_utils},
+};
+use clap::Subcommand;
+use std::error::Error;
+
+#[derive(Subcommand, PartialEq)]
+pub enum TrashCommands {
+    /// Show the deleted time blocks and their ids
+    List,
+    /// Move deleted time blocks back. E.g., zyr trash restore 12 13
+    Restore {
+        /// The ids of the time blocks, as shown by `zyr trash list`
+        #[arg(required = true)]
+        ids: Vec<u64>,
+    },
+    /// Permanently delete all time blocks in the trash
+    Empty,
+}
+
+impl Executable for TrashCommands {
+    fn execute(&self, data: &mut Data, _store: &mut Store) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::List => Self::exec_list(data),
+            Self::Restore { ids } => Self::exec_restore(ids, data)?,
+            Self::Empty => Self::exec_empty(data),
+        }
+        Ok(())
+    }
+}
+
+impl TrashCommands {
+    /// Implementation of the `zyr trash list` command
+    fn exec_list(data: &Data) {
+        if data.trash.is_empty() {
+            println!("The trash is empty");
+            return;
+        }
+
+        for trashed in data.trash.iter().rev() {
+            println!(
+                "{:>5}  deleted {}  {}",
+                trashed.block.id,
+                time_utils::convert(trashed.deleted_unix).format("%Y-%m-%d %H:%M:%S"),
+                trashed.block
+            );
+        }
+        println!(
+            "Deleted time blocks are removed after {} days",
+            data.settings.trash_days
+        );
+    }
+
+    /// Implementation of the `zyr trash restore` command
+    fn exec_restore(ids: &[u64], data: &mut Data) -> Result<(), Box<dyn Error>> {
+        if let Some(id) = ids
+            .iter()
+            .find(|&&id| !data.trash.iter().any(|t| t.block.id == id))
+        {
+            return Err(format!(
+                "There is no time block with id {id} in the trash. See `zyr trash list` for all deleted blocks."
+            )
+            .into());
+        }
+
+        for &id in ids {
+            if let Some(block) = data.restore_block(id) {
+                println!("Restored {block}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Implementation of the `zyr trash empty` command
+    fn exec_empty(data: &mut Data) {
+        if data.trash.is_empty() {
+            println!("The trash is already empty");
+        } else if io_utils::confirm(&format!(
+            "permanently delete {} time blocks",
+            data.trash.len()
+        )) {
+            data.trash.clear();
+            println!("The trash has been emptied");
+        } else {
+            println!("The trash was not emptied");
+        }
+    }
+}
@@ -1,16 +1,48 @@
 use crate::domain::Data;
 use crate::utils::time_utils;
-use chrono::Local;
+use chrono::{DateTime, Local};
 use std::collections::HashMap;
 use std::time::Duration;
 
 /// Implementation of the `zyr view` command
 pub fn exec(data: &Data) {
-    let now_dt = Local::now();
+    let filtered = category_totals(data, Local::now());
 
+    let time_worked: Duration = filtered
+        .iter()
+        .filter(|t| t.1 != "break")
+        .fold(Duration::from_millis(0), |t, c| t + c.0);
+
+    let time_break: Duration = filtered
+        .iter()
+        .filter(|t| t.1 == "break")
+        .fold(Duration::from_millis(0), |t, c| t + c.0);
+
+    let breakdown: String = filtered
+        .iter()
+        .map(|(d, c)| format!("{}: {}", c, time_utils::prettify_duration(*d)))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    println!(
+        "Overview:\n\nTime worked: {}\nBreak time: {}\nTotal: {}\n",
+        time_utils::prettify_duration(time_worked),
+        time_utils::prettify_duration(time_break),
+        time_utils::prettify_duration(time_worked + time_break)
+    );
+
+    println!("Breakdown by category:\n\n{breakdown}");
+}
+
+/// Sum up the tracked time of each category on the day of `now_dt`, up to `now_dt`.
+/// Planned blocks are not counted.
+///
+/// * return - The durations and categories, longest first
+fn category_totals(data: &Data, now_dt: DateTime<Local>) -> Vec<(Duration, &str)> {
     let mut filtered: Vec<(Duration, &str)> = data
         .blocks
         .iter()
+        .filter(|b| !b.planned)
         .filter(|b| {
             time_utils::same_day(now_dt, time_utils::convert(b.start_unix))
                 || (b.end_unix.is_some()
@@ -46,29 +78,34 @@ pub fn exec(data: &Data) {
         .map(|(s, d)| (d, s))
         .collect::<Vec<(Duration, &str)>>();
     filtered.sort_by(|a, b| b.cmp(a));
+    filtered
+}
 
-    let time_worked: Duration = filtered
-        .iter()
-        .filter(|t| t.1 != "break")
-        .fold(Duration::from_millis(0), |t, c| t + c.0);
-
-    let time_break: Duration = filtered
-        .iter()
-        .filter(|t| t.1 == "break")
-        .fold(Duration::from_millis(0), |t, c| t + c.0);
-
-    let breakdown: String = filtered
-        .iter()
-        .map(|(d, c)| format!("{}: {}", c, time_utils::prettify_duration(*d)))
-        .collect::<Vec<String>>()
-        .join("\n");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TimeBlock;
 
-    println!(
-        "Overview:\n\nTime worked: {}\nBreak time: {}\nTotal: {}\n",
-        time_utils::prettify_duration(time_worked),
-        time_utils::prettify_duration(time_break),
-        time_utils::prettify_duration(time_worked + time_break)
-    );
+    #[test]
+    fn test_category_totals_skip_planned_blocks() {
+        let noon = chrono::NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        let now = time_utils::at_time(Local::now().date_naive(), noon);
+        let now_dt = time_utils::convert(now);
+        let block = |id: u64, category: &str, planned: bool| TimeBlock {
+            id,
+            start_unix: now - 1000,
+            end_unix: Some(now),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned,
+        };
+        let mut data = Data::new();
+        data.blocks = vec![block(1, "code", false), block(2, "review", true)];
 
-    println!("Breakdown by category:\n\n{breakdown}");
+        assert_eq!(
+            category_totals(&data, now_dt),
+            vec![(Duration::from_millis(1000), "code")]
+        );
+    }
 }
@@ -1,10 +1,15 @@
-use crate::utils::time_utils;
+use crate::storage::{Store, migrations};
+use crate::utils::{file_utils, parsers, time_utils};
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Weekday};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
-use std::fs::{self, File};
-use std::io::Write;
+use std::fs;
+use std::io;
 use std::path::Path;
+use std::str::FromStr;
 use std::time::Duration;
 
 /// Type alias for a Unix timestamp
@@ -29,9 +34,15 @@ impl Timer {
 
     pub fn with_duration(duration: Duration) -> Self {
         let now: u64 = time_utils::since_unix().as_millis() as u64;
+        Self::with_duration_from(now, duration)
+    }
+
+    /// Create a countdown timer that started at `start_unix`, e.g., right when the previous
+    /// timer of a queue ended.
+    pub fn with_duration_from(start_unix: u64, duration: Duration) -> Self {
         Self {
-            start_unix: now,
-            end_unix: Some(now + duration.as_millis() as u64),
+            start_unix,
+            end_unix: Some(start_unix + duration.as_millis() as u64),
         }
     }
 
@@ -73,11 +84,23 @@ impl Timer {
         (hours, minutes, total_seconds % 60)
     }
 
-    pub fn to_block(&self, category: &str) -> TimeBlock {
+    /// Create a timer for a time block, which runs until the block ends.
+    pub fn from_block(block: &TimeBlock) -> Self {
+        Self {
+            start_unix: block.start_unix,
+            end_unix: block.end_unix,
+        }
+    }
+
+    pub fn to_block(&self, id: u64, category: &str) -> TimeBlock {
         TimeBlock {
+            id,
             start_unix: self.start_unix,
             end_unix: self.end_unix,
             category: category.to_owned(),
+            note: None,
+            tags: vec![],
+            planned: false,
         }
     }
 }
@@ -90,11 +113,52 @@ impl fmt::Display for Timer {
 }
 
 /// A block of work with a start and end time.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TimeBlock {
+    /// Unique among all blocks of the same `Data`. 0 for blocks that have not been given one yet.
+    #[serde(default)]
+    pub id: u64,
     pub start_unix: u64,
     pub end_unix: Option<u64>,
     pub category: String,
+    /// A free text description of what was done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Labels that group blocks across categories, e.g., a project or a client
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Whether the block is part of a schedule instead of time that was tracked
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub planned: bool,
+}
+
+impl TimeBlock {
+    /// The end of the block, or `now` if it is still running.
+    pub fn end_or(&self, now: u64) -> u64 {
+        self.end_unix.unwrap_or(now)
+    }
+
+    /// Check if two blocks share any moment. Running blocks last until `now`. Planned blocks
+    /// only overlap other planned blocks, so that a plan and what actually happened can coexist.
+    pub fn overlaps(&self, other: &TimeBlock, now: u64) -> bool {
+        self.planned == other.planned
+            && self.start_unix < other.end_or(now)
+            && other.start_unix < self.end_or(now)
+    }
+
+    /// Move the start and end of the block by `offset` milliseconds, which may be negative.
+    ///
+    /// * return - The moved block, or an error if it would start before the Unix epoch
+    pub fn shifted(&self, offset: i64) -> Result<TimeBlock, String> {
+        let move_by = |unix: u64| {
+            unix.checked_add_signed(offset)
+                .ok_or_else(|| format!("Time block {} cannot be moved that far", self.id))
+        };
+        let mut block = self.clone();
+        block.start_unix = move_by(self.start_unix)?;
+        block.end_unix = self.end_unix.map(move_by).transpose()?;
+        Ok(block)
+    }
 }
 
 impl fmt::Display for TimeBlock {
@@ -106,70 +170,1182 @@ impl fmt::Display for TimeBlock {
         };
         write!(
             f,
-            "{}: {}, {}",
+            "{}{}: {}, {}",
+            if self.planned { "planned " } else { "" },
             self.category,
             start_dt.to_rfc2822(),
             end_str
+        )?;
+        if let Some(note) = &self.note {
+            write!(f, " ({note})")?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " [{}]", self.tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A deleted time block, kept in the trash until it is restored or purged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrashedBlock {
+    pub block: TimeBlock,
+    /// When the block was deleted, in milliseconds since the Unix epoch
+    pub deleted_unix: u64,
+}
+
+/// A way for the user to point at a time block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockRef {
+    /// The id of the block, written with a leading `#`, e.g. `#12`
+    Id(u64),
+    /// 0 for the most recent tracked block, 1 for the second most recent, etc.
+    Order(u32),
+    /// Like `Order`, but counting planned blocks, written with a leading `p`, e.g. `p0`
+    Planned(u32),
+}
+
+impl FromStr for BlockRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(id) = s.strip_prefix('#') {
+            return id
+                .parse()
+                .map(Self::Id)
+                .map_err(|_| format!("Invalid block id {s}, expected e.g. #12"));
+        }
+        match s.strip_prefix('p') {
+            Some(order) => order.parse().map(Self::Planned).map_err(|_| {
+                format!("Invalid order number {s}, expected e.g. p0 for a planned block")
+            }),
+            None => s
+                .parse()
+                .map(Self::Order)
+                .map_err(|_| format!("Invalid order number {s}, expected e.g. 0 or #12 for an id")),
+        }
+    }
+}
+
+/// What to do with existing time blocks that overlap a new or edited one.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OverlapPolicy {
+    /// Refuse the change
+    Reject,
+    /// Shorten the existing blocks, deleting the ones that are covered completely
+    Trim,
+    /// Like trim, but cut an existing block that covers the new one into two parts around it
+    Split,
+    /// Keep the overlapping blocks as they are
+    Allow,
+}
+
+/// A time block that repeats on certain weekdays at the same local time of day. It is turned
+/// into time blocks by `Data::apply_recurrences`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Recurrence {
+    pub id: u64,
+    pub category: String,
+    /// The days the block repeats on, counted from 0 for Monday to 6 for Sunday
+    pub weekdays: Vec<u32>,
+    /// The local time of day the block starts at, e.g., 09:30
+    pub start: String,
+    /// The local time of day the block ends at, e.g., 09:45
+    pub end: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The start of the first day that has not been turned into time blocks yet, in
+    /// milliseconds since the Unix epoch
+    pub applied_until: u64,
+}
+
+impl Recurrence {
+    /// Create the time block of this recurrence on `date`.
+    ///
+    /// * return - The block, or `None` if the recurrence does not repeat on that day
+    fn block_on(&self, date: NaiveDate, id: u64) -> Option<TimeBlock> {
+        if !self
+            .weekdays
+            .contains(&date.weekday().num_days_from_monday())
+        {
+            return None;
+        }
+        let (start, end) = (
+            parsers::parse_time(&self.start).ok()?,
+            parsers::parse_time(&self.end).ok()?,
+        );
+        Some(TimeBlock {
+            id,
+            start_unix: time_utils::at_time(date, start),
+            end_unix: Some(time_utils::at_time(date, end)),
+            category: self.category.clone(),
+            note: self.note.clone(),
+            tags: self.tags.clone(),
+            planned: true,
+        })
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = match self.weekdays.as_slice() {
+            [0, 1, 2, 3, 4, 5, 6] => "daily".to_string(),
+            [0, 1, 2, 3, 4] => "weekdays".to_string(),
+            [5, 6] => "weekends".to_string(),
+            days => {
+                let names: Vec<String> = days
+                    .iter()
+                    .filter_map(|&d| Weekday::try_from(d as u8).ok())
+                    .map(|d| d.to_string())
+                    .collect();
+                names.join(",")
+            }
+        };
+        write!(f, "{}: {days} {}-{}", self.category, self.start, self.end)?;
+        if let Some(note) = &self.note {
+            write!(f, " ({note})")?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " [{}]", self.tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The settings of a timer that is started by name, e.g., with `zyr timer start @focus`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub category: String,
+    /// The countdown of the timer, or `None` for a timer without an end
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@{} = {}", self.name, self.category)?;
+        if let Some(ms) = self.duration_ms {
+            write!(
+                f,
+                ", {}",
+                time_utils::prettify_duration(Duration::from_millis(ms))
+            )?;
+        }
+        if let Some(note) = &self.note {
+            write!(f, " ({note})")?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " [{}]", self.tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A countdown timer waiting to be started by `zyr timer queue`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueuedTimer {
+    pub category: String,
+    pub duration_ms: u64,
+}
+
+impl fmt::Display for QueuedTimer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({})",
+            self.category,
+            time_utils::prettify_duration(Duration::from_millis(self.duration_ms))
         )
     }
 }
 
+/// Countdown timers that run back-to-back, started with `zyr timer queue`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TimerQueue {
+    /// The timers that have not started yet, next one first
+    pub waiting: Vec<QueuedTimer>,
+    /// The id of the time block of the queued timer that was started last
+    pub current: Option<u64>,
+}
+
+/// A named plan for a whole day, saved from the blocks of an existing day with
+/// `zyr template save`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    /// The blocks of the day, in chronological order
+    pub blocks: Vec<TemplateBlock>,
+}
+
+/// A time block of a `Template`, which starts and ends at a local time of day.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemplateBlock {
+    pub category: String,
+    /// The local time of day the block starts at, e.g., 09:30
+    pub start: String,
+    /// The local time of day the block ends at, e.g., 11:00. An end before the start is on the
+    /// next day.
+    pub end: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl Template {
+    /// Create a template from time blocks, keeping their local times of day.
+    pub fn from_blocks(name: &str, blocks: &[&TimeBlock]) -> Self {
+        let time_of = |unix: u64| time_utils::convert(unix).format("%H:%M").to_string();
+        Self {
+            name: name.to_string(),
+            blocks: blocks
+                .iter()
+                .map(|b| TemplateBlock {
+                    category: b.category.clone(),
+                    start: time_of(b.start_unix),
+                    end: time_of(b.end_unix.unwrap_or(b.start_unix)),
+                    note: b.note.clone(),
+                    tags: b.tags.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Create the planned time blocks of the template on `date`. They have not been given ids
+    /// yet.
+    ///
+    /// * return - The blocks, or an error if the template contains an invalid time
+    pub fn blocks_on(&self, date: NaiveDate) -> Result<Vec<TimeBlock>, String> {
+        let mut blocks = vec![];
+        for block in &self.blocks {
+            let (start, end) = (
+                parsers::parse_time(&block.start)?,
+                parsers::parse_time(&block.end)?,
+            );
+            let end_date = if end < start {
+                date + Days::new(1)
+            } else {
+                date
+            };
+            blocks.push(TimeBlock {
+                id: 0,
+                start_unix: time_utils::at_time(date, start),
+                end_unix: Some(time_utils::at_time(end_date, end)),
+                category: block.category.clone(),
+                note: block.note.clone(),
+                tags: block.tags.clone(),
+                planned: true,
+            });
+        }
+        Ok(blocks)
+    }
+}
+
+/// User preferences, changed with `zyr config set`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// For how many days daily backups and backups taken before `zyr clear` are kept
+    pub backup_days: u32,
+    /// How many backups taken before individual changes are kept
+    pub backup_changes: u32,
+    /// For how many days deleted time blocks are kept in the trash
+    pub trash_days: u32,
+    /// When the working day starts, e.g., 09:00. `zyr plan gaps` looks for untracked time
+    /// between the start and the end of the working day.
+    pub work_start: String,
+    /// When the working day ends, e.g., 17:00
+    pub work_end: String,
+}
+
+impl Settings {
+    /// Parse the start and end of the working day.
+    ///
+    /// * return - The start and end, or an error if they are no valid times of day
+    pub fn work_hours(&self) -> Result<(NaiveTime, NaiveTime), String> {
+        let parse = |s: &str| {
+            NaiveTime::parse_from_str(s, "%H:%M")
+                .map_err(|_| format!("Invalid working hours {s}, expected e.g. 09:00"))
+        };
+        let (start, end) = (parse(&self.work_start)?, parse(&self.work_end)?);
+        if end <= start {
+            return Err(format!(
+                "The working day ends at {} before it starts at {}",
+                self.work_end, self.work_start
+            ));
+        }
+        Ok((start, end))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backup_days: 14,
+            backup_changes: 50,
+            trash_days: 30,
+            work_start: "09:00".to_string(),
+            work_end: "17:00".to_string(),
+        }
+    }
+}
+
 /// A struct containing all user data that `zyr` persists on the user's device.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Data {
+    schema_version: u32,
     categories: Vec<String>,
     pub blocks: Vec<TimeBlock>, //TODO: Expose block methods in interface
+    #[serde(default)]
+    pub settings: Settings,
+    /// Deleted time blocks, oldest deletion first
+    #[serde(default)]
+    pub trash: Vec<TrashedBlock>,
+    /// Time blocks that repeat, managed with `zyr plan recur`
+    #[serde(default)]
+    pub recurrences: Vec<Recurrence>,
+    /// Plans for whole days, managed with `zyr template`
+    #[serde(default)]
+    pub templates: Vec<Template>,
+    /// Timers waiting to run, managed with `zyr timer queue`
+    #[serde(default)]
+    pub queue: TimerQueue,
+    /// Named timers, managed with `zyr timer preset`
+    #[serde(default)]
+    pub presets: Vec<Preset>,
 }
 
 /// A representation of the user data stored on disk by the program.
 impl Data {
     pub fn empty() -> Self {
         Self {
+            schema_version: migrations::CURRENT_VERSION,
             categories: vec![],
             blocks: vec![],
+            settings: Settings::default(),
+            trash: vec![],
+            recurrences: vec![],
+            templates: vec![],
+            queue: TimerQueue::default(),
+            presets: vec![],
         }
     }
 
     pub fn new() -> Self {
         Self {
+            schema_version: migrations::CURRENT_VERSION,
             categories: vec![String::from("break")],
             blocks: vec![],
+            settings: Settings::default(),
+            trash: vec![],
+            recurrences: vec![],
+            templates: vec![],
+            queue: TimerQueue::default(),
+            presets: vec![],
         }
     }
 
-    pub fn from_file(path: &Path) -> Self {
-        let json_str = fs::read_to_string(path).expect("File could not be read");
-        serde_json::from_str(&json_str).expect("JSON could not be parsed")
+    pub fn from_file(path: &Path) -> Result<Self, LoadError> {
+        Self::read_file(path).map(|(data, _)| data)
+    }
+
+    /// Read a data file, upgrading it to the current schema version if it is older.
+    ///
+    /// * return - The data and the schema version the file was stored with, or an error
+    pub fn read_file(path: &Path) -> Result<(Self, u32), LoadError> {
+        let json_str = fs::read_to_string(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => LoadError::Missing,
+            _ => LoadError::Unreadable(e),
+        })?;
+        let mut value: serde_json::Value =
+            serde_json::from_str(&json_str).map_err(LoadError::Corrupted)?;
+        let version = migrations::migrate(&mut value).map_err(LoadError::Incompatible)?;
+
+        // Parsing the original text keeps the location of errors in the message
+        let data = if version == migrations::CURRENT_VERSION {
+            serde_json::from_str(&json_str)
+        } else {
+            serde_json::from_value(value)
+        };
+        Ok((data.map_err(LoadError::Corrupted)?, version))
     }
 
-    pub fn save(&self, path: &Path) {
-        let mut file = File::create(path).expect("File could not be opened");
+    /// Write the data to `path` without ever leaving a partially written file behind.
+    ///
+    /// The previous contents of the file are kept next to it with a `.bak` extension.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
         let stringified = serde_json::to_string(self).expect("Object could not be serialized");
-        file.write_all(stringified.as_bytes())
-            .expect("Could not write to file");
+        file_utils::write_atomic(path, stringified.as_bytes())
     }
 
-    pub fn get_running_timer(&self) -> Option<Timer> {
+    /// Return an id that no time block uses yet, including the ones in the trash.
+    pub fn next_block_id(&self) -> u64 {
+        let trashed = self.trash.iter().map(|t| &t.block);
         self.blocks
             .iter()
-            .filter(|b| {
-                if let Some(end) = b.end_unix {
-                    let now = time_utils::since_unix().as_millis() as u64;
-                    end > now
-                } else {
-                    true
+            .chain(trashed)
+            .map(|b| b.id)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Sort the time blocks by start time, so that order numbers count back from the most
+    /// recent block. Blocks starting at the same moment keep their order.
+    pub fn sort_blocks(&mut self) {
+        self.blocks.sort_by_key(|b| b.start_unix);
+    }
+
+    /// Add a time block at the position that keeps the blocks sorted by start time.
+    ///
+    /// * return - The index of the new block
+    pub fn insert_block(&mut self, block: TimeBlock) -> usize {
+        let index = self
+            .blocks
+            .partition_point(|b| b.start_unix <= block.start_unix);
+        self.blocks.insert(index, block);
+        index
+    }
+
+    /// Find the position of the time block a reference points at.
+    ///
+    /// * return - The index in the array of time blocks, or an error if there is no such block
+    pub fn resolve_block(&self, block: BlockRef) -> Result<usize, String> {
+        match block {
+            BlockRef::Id(id) => self.block_index(id).ok_or_else(|| {
+                format!("There is no time block with id {id}. Deleted blocks are listed by `zyr trash list`.")
+            }),
+            BlockRef::Order(order) => self.nth_latest(order, false),
+            BlockRef::Planned(order) => self.nth_latest(order, true),
+        }
+    }
+
+    /// Find the position of the `order`-th most recent block that is planned or tracked.
+    fn nth_latest(&self, order: u32, planned: bool) -> Result<usize, String> {
+        let indices: Vec<usize> = (0..self.blocks.len())
+            .rev()
+            .filter(|&i| self.blocks[i].planned == planned)
+            .collect();
+        indices.get(order as usize).copied().ok_or_else(|| {
+            format!(
+                "This order number does not exist. The number you selected was {}, which is greater than the total number of {} blocks, which is {}",
+                order,
+                if planned { "planned" } else { "tracked" },
+                indices.len()
+            )
+        })
+    }
+
+    /// Find the position of the time block with the given id.
+    pub fn block_index(&self, id: u64) -> Option<usize> {
+        self.blocks.iter().position(|b| b.id == id)
+    }
+
+    /// Add a time block, or replace the one with the same id, and make room for it according to
+    /// `policy`. Nothing is changed if the policy rejects the block.
+    ///
+    /// * return - Nothing, or an error listing the overlapping blocks
+    pub fn put_block(&mut self, block: TimeBlock, policy: OverlapPolicy) -> Result<(), String> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let overlapping: Vec<u64> = self
+            .blocks
+            .iter()
+            .filter(|b| b.id != block.id && b.overlaps(&block, now))
+            .map(|b| b.id)
+            .collect();
+
+        if policy == OverlapPolicy::Reject && !overlapping.is_empty() {
+            let list: Vec<String> = self
+                .blocks
+                .iter()
+                .filter(|b| overlapping.contains(&b.id))
+                .map(|b| format!("  {b}"))
+                .collect();
+            return Err(format!(
+                "The time block overlaps with:\n{}\nUse --on-overlap trim or --on-overlap split to adjust them, or --allow-overlap to keep them as they are.",
+                list.join("\n")
+            ));
+        }
+
+        if let Some(i) = self.block_index(block.id) {
+            self.blocks.remove(i);
+        }
+        self.insert_block(block.clone());
+        if policy == OverlapPolicy::Allow {
+            return Ok(());
+        }
+
+        for id in overlapping {
+            let Some(i) = self.block_index(id) else {
+                continue;
+            };
+            let existing = &mut self.blocks[i];
+            let starts_before = existing.start_unix < block.start_unix;
+            let ends_after = existing.end_or(now) > block.end_or(now);
+
+            if starts_before && ends_after && policy == OverlapPolicy::Split {
+                let mut rest = existing.clone();
+                existing.end_unix = Some(block.start_unix);
+                rest.id = self.next_block_id();
+                rest.start_unix = block.end_or(now);
+                self.insert_block(rest);
+            } else if starts_before {
+                existing.end_unix = Some(block.start_unix);
+            } else if ends_after {
+                existing.start_unix = block.end_or(now);
+            } else {
+                self.trash_block(i);
+            }
+        }
+        self.sort_blocks();
+        Ok(())
+    }
+
+    /// Replace time blocks with a single block spanning all of them, which keeps the id, category
+    /// and position of the earliest one. Their notes are combined. Nothing is changed if `policy`
+    /// rejects the merged block.
+    ///
+    /// * `ids`      - The ids of the blocks to merge
+    /// * `category` - The category of the merged block, instead of the one of the earliest block
+    ///
+    /// * return - The merged block, or an error listing the blocks it overlaps
+    pub fn merge_blocks(
+        &mut self,
+        ids: &[u64],
+        category: Option<&str>,
+        policy: OverlapPolicy,
+    ) -> Result<TimeBlock, String> {
+        let mut blocks: Vec<&TimeBlock> =
+            self.blocks.iter().filter(|b| ids.contains(&b.id)).collect();
+        blocks.sort_by_key(|b| b.start_unix);
+        let Some(&first) = blocks.first() else {
+            return Err("There are no time blocks to merge".to_string());
+        };
+
+        let mut merged = first.clone();
+        merged.end_unix = blocks
+            .iter()
+            .map(|b| b.end_unix)
+            .reduce(|a, b| a.zip(b).map(|(a, b)| a.max(b)))
+            .flatten();
+        if let Some(c) = category {
+            merged.category = c.to_string();
+        }
+        let mut notes: Vec<&str> = vec![];
+        for note in blocks.iter().filter_map(|b| b.note.as_deref()) {
+            if !notes.contains(&note) {
+                notes.push(note);
+            }
+        }
+        merged.note = (!notes.is_empty()).then(|| notes.join("; "));
+
+        let mut result = self.clone();
+        result
+            .blocks
+            .retain(|b| b.id == merged.id || !ids.contains(&b.id));
+        result.put_block(merged.clone(), policy)?;
+        *self = result;
+        Ok(merged)
+    }
+
+    /// Move time blocks together by `offset` milliseconds, which keeps the gaps between them.
+    /// Nothing is changed if `policy` rejects any of the moved blocks.
+    ///
+    /// * `ids`    - The ids of the blocks to move
+    /// * `offset` - How far to move the blocks, negative to move them earlier
+    ///
+    /// * return - Nothing, or an error listing the blocks they would overlap
+    pub fn shift_blocks(
+        &mut self,
+        ids: &[u64],
+        offset: i64,
+        policy: OverlapPolicy,
+    ) -> Result<(), String> {
+        let mut result = self.clone();
+        let mut moved = vec![];
+        for block in self.blocks.iter().filter(|b| ids.contains(&b.id)) {
+            moved.push(block.shifted(offset)?);
+        }
+        // The moved blocks only have to make room among the blocks that stay in place
+        result.blocks.retain(|b| !ids.contains(&b.id));
+        for block in moved {
+            result.put_block(block, policy)?;
+        }
+        *self = result;
+        Ok(())
+    }
+
+    /// Copy time blocks onto other days, keeping their local times of day. The copies get new
+    /// ids. Nothing is changed if `policy` rejects any of the copies.
+    ///
+    /// * `ids`  - The ids of the blocks to copy
+    /// * `days` - How many days after the original blocks the copies are, negative for earlier
+    ///
+    /// * return - The copies, or an error listing the blocks they would overlap
+    pub fn copy_blocks(
+        &mut self,
+        ids: &[u64],
+        days: i64,
+        policy: OverlapPolicy,
+    ) -> Result<Vec<TimeBlock>, String> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut result = self.clone();
+        let mut copies = vec![];
+        for block in self.blocks.iter().filter(|b| ids.contains(&b.id)) {
+            let Some(end) = block.end_unix else {
+                return Err(format!(
+                    "Time block {} is still running and cannot be copied",
+                    block.id
+                ));
+            };
+            let mut copy = block.clone();
+            copy.id = result.next_block_id();
+            copy.start_unix = time_utils::shift_days(block.start_unix, days);
+            copy.end_unix = Some(time_utils::shift_days(end, days));
+            // Time in the future cannot have been tracked yet
+            copy.planned |= copy.start_unix > now;
+            result.put_block(copy.clone(), policy)?;
+            copies.push(copy);
+        }
+        *self = result;
+        Ok(copies)
+    }
+
+    /// Find the intervals between `from` and `to` that no tracked time block covers.
+    ///
+    /// * return - The start and end of every gap, in chronological order
+    pub fn gaps(&self, from: u64, to: u64) -> Vec<(u64, u64)> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut gaps = vec![];
+        let mut covered_until = from;
+        let tracked = self.blocks.iter().filter(|b| !b.planned);
+        for block in tracked.take_while(|b| b.start_unix < to) {
+            if block.start_unix > covered_until {
+                gaps.push((covered_until, block.start_unix));
+            }
+            covered_until = covered_until.max(block.end_or(now));
+        }
+        if covered_until < to {
+            gaps.push((covered_until, to));
+        }
+        gaps
+    }
+
+    /// Collect the ids of all time blocks that overlap another one.
+    pub fn overlapping_ids(&self) -> HashSet<u64> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut by_start: Vec<&TimeBlock> = self.blocks.iter().collect();
+        by_start.sort_by_key(|b| b.start_unix);
+
+        let mut ids = HashSet::new();
+        for (i, block) in by_start.iter().enumerate() {
+            for other in by_start[i + 1..]
+                .iter()
+                .take_while(|o| o.start_unix < block.end_or(now))
+            {
+                if block.overlaps(other, now) {
+                    ids.insert(block.id);
+                    ids.insert(other.id);
                 }
-            })
-            .map(|b| Timer {
-                start_unix: b.start_unix,
-                end_unix: b.end_unix,
-            })
-            .next()
+            }
+        }
+        ids
+    }
+
+    /// Move the time block at `index` to the trash.
+    pub fn trash_block(&mut self, index: usize) {
+        let block = self.blocks.remove(index);
+        self.trash.push(TrashedBlock {
+            block,
+            deleted_unix: time_utils::since_unix().as_millis() as u64,
+        });
+    }
+
+    /// Move a time block from the trash back to the other blocks.
+    ///
+    /// * `id` - The id of the trashed block
+    ///
+    /// * return - The restored block, or `None` if there is no block with this id in the trash
+    pub fn restore_block(&mut self, id: u64) -> Option<&TimeBlock> {
+        let index = self.trash.iter().position(|t| t.block.id == id)?;
+        let mut block = self.trash.remove(index).block;
+        if self.blocks.iter().any(|b| b.id == block.id) {
+            block.id = self.next_block_id();
+        }
+
+        let index = self.insert_block(block);
+        Some(&self.blocks[index])
+    }
+
+    /// Turn every recurrence into time blocks for the days from the first day it has not been
+    /// applied to until `until`. Days that already have an overlapping block are skipped, so that
+    /// recurrences never change existing blocks.
+    ///
+    /// * return - The number of created blocks and the number of skipped days
+    pub fn apply_recurrences(&mut self, until: NaiveDate) -> (usize, usize) {
+        let (mut created, mut skipped) = (0, 0);
+        let applied_until = time_utils::start_of_day(until + Days::new(1));
+        for i in 0..self.recurrences.len() {
+            let recurrence = self.recurrences[i].clone();
+            let first = time_utils::convert(recurrence.applied_until).date_naive();
+            for date in first.iter_days().take_while(|d| *d <= until) {
+                let Some(block) = recurrence.block_on(date, self.next_block_id()) else {
+                    continue;
+                };
+                match self.put_block(block, OverlapPolicy::Reject) {
+                    Ok(()) => created += 1,
+                    Err(_) => skipped += 1,
+                }
+            }
+            let recurrence = &mut self.recurrences[i];
+            recurrence.applied_until = recurrence.applied_until.max(applied_until);
+        }
+        (created, skipped)
+    }
+
+    /// Start the next queued timers once the current one has finished. Each starts when the
+    /// last tracked block ended, so that timers that finished while `zyr` was not running are
+    /// recorded as if they had run back-to-back.
+    ///
+    /// * return - The blocks of the started timers
+    pub fn advance_queue(&mut self) -> Vec<TimeBlock> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut started = vec![];
+        while !self.queue.waiting.is_empty() && self.running_block_index().is_none() {
+            let start = match self.queue.current.and_then(|id| self.block_index(id)) {
+                Some(i) => {
+                    let current_start = self.blocks[i].start_unix;
+                    self.blocks
+                        .iter()
+                        .filter(|b| !b.planned && b.start_unix >= current_start)
+                        .filter_map(|b| b.end_unix)
+                        .filter(|&end| end <= now)
+                        .max()
+                        .unwrap_or(now)
+                }
+                // The block of the previous timer was deleted
+                None => now,
+            };
+
+            let next = self.queue.waiting.remove(0);
+            let timer = Timer::with_duration_from(start, Duration::from_millis(next.duration_ms));
+            let block = timer.to_block(self.next_block_id(), &next.category);
+            self.queue.current = Some(block.id);
+            self.insert_block(block.clone());
+            started.push(block);
+        }
+        started
+    }
+
+    /// Permanently remove the time blocks that have been in the trash for longer than
+    /// `Settings::trash_days`.
+    ///
+    /// * return - The number of removed blocks
+    pub fn purge_trash(&mut self) -> usize {
+        let max_age = u64::from(self.settings.trash_days) * 24 * 60 * 60 * 1000;
+        let now = time_utils::since_unix().as_millis() as u64;
+        let before = self.trash.len();
+        self.trash
+            .retain(|t| now.saturating_sub(t.deleted_unix) <= max_age);
+        before - self.trash.len()
+    }
+
+    /// Give a new id to every time block that has none or shares its id with an earlier block.
+    pub fn fix_block_ids(&mut self) {
+        let mut seen = HashSet::new();
+        let mut next_id = self.next_block_id();
+        for block in &mut self.blocks {
+            if block.id == 0 || !seen.insert(block.id) {
+                block.id = next_id;
+                next_id += 1;
+            }
+        }
+    }
+
+    /// Find the time block of the running timer, which is the most recent tracked block that
+    /// has started but not ended yet.
+    pub fn running_block_index(&self) -> Option<usize> {
+        let now = time_utils::since_unix().as_millis() as u64;
+        self.blocks.iter().rposition(|b| {
+            !b.planned && b.start_unix <= now && b.end_unix.is_none_or(|end| end > now)
+        })
+    }
+
+    pub fn get_running_timer(&self) -> Option<Timer> {
+        self.running_block_index()
+            .map(|i| Timer::from_block(&self.blocks[i]))
+    }
+}
+
+/// The reasons why the data file could not be turned into `Data`.
+#[derive(Debug)]
+pub enum LoadError {
+    Missing,
+    Unreadable(io::Error),
+    Corrupted(serde_json::Error),
+    Incompatible(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "The data file does not exist"),
+            Self::Unreadable(e) => write!(f, "The data file could not be read: {e}"),
+            Self::Corrupted(e) => write!(f, "The data file is corrupted: {e}"),
+            Self::Incompatible(e) => write!(f, "{e}"),
+        }
     }
 }
 
+impl Error for LoadError {}
+
 /// A CLI command that uses the user's data
 pub trait Executable {
     /// Run the CLI command
-    fn execute(&self, data: &mut Data) -> Result<(), Box<dyn Error>>;
+    fn execute(&self, data: &mut Data, store: &mut Store) -> Result<(), Box<dyn Error>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(id: u64, start_unix: u64, end_unix: u64) -> TimeBlock {
+        TimeBlock {
+            id,
+            start_unix,
+            end_unix: Some(end_unix),
+            category: "code".to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    #[test]
+    fn test_put_block_overlap_policies() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 100, 200)];
+
+        assert!(
+            data.put_block(block(3, 50, 60), OverlapPolicy::Reject)
+                .is_err()
+        );
+        assert_eq!(data.blocks.len(), 2);
+
+        data.put_block(block(3, 50, 60), OverlapPolicy::Split)
+            .unwrap();
+        let spans: Vec<(u64, u64)> = data
+            .blocks
+            .iter()
+            .map(|b| (b.start_unix, b.end_unix.unwrap()))
+            .collect();
+        assert_eq!(spans, vec![(0, 50), (50, 60), (60, 100), (100, 200)]);
+
+        data.put_block(block(5, 40, 150), OverlapPolicy::Trim)
+            .unwrap();
+        let ids: Vec<u64> = data.blocks.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![1, 5, 2]);
+        assert_eq!(data.blocks[0].end_unix, Some(40));
+        assert_eq!(data.blocks[2].start_unix, 150);
+        assert_eq!(data.trash.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_blocks_spans_all() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 150, 200), block(3, 300, 400)];
+        data.blocks[1].note = Some("review".to_string());
+
+        // Merging around a block in between is rejected without changing anything
+        assert!(
+            data.merge_blocks(&[1, 3], None, OverlapPolicy::Reject)
+                .is_err()
+        );
+        assert_eq!(data.blocks.len(), 3);
+
+        let merged = data
+            .merge_blocks(&[2, 1], None, OverlapPolicy::Reject)
+            .unwrap();
+        assert_eq!(
+            (merged.id, merged.start_unix, merged.end_unix),
+            (1, 0, Some(200))
+        );
+        assert_eq!(merged.note.as_deref(), Some("review"));
+        assert_eq!(data.blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_gaps_between_blocks() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 50, 200), block(3, 300, 400)];
+
+        assert_eq!(data.gaps(0, 500), vec![(200, 300), (400, 500)]);
+        assert_eq!(data.gaps(120, 350), vec![(200, 300)]);
+        assert_eq!(data.gaps(300, 400), vec![]);
+    }
+
+    #[test]
+    fn test_copy_blocks_gets_new_ids() {
+        let day = 24 * 60 * 60 * 1000;
+        let mut data = Data::new();
+        data.blocks = vec![block(1, day, day + 100), block(2, day + 200, day + 300)];
+
+        let copies = data.copy_blocks(&[1, 2], 1, OverlapPolicy::Reject).unwrap();
+        assert_eq!(copies.iter().map(|b| b.id).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(data.blocks.len(), 4);
+        assert_eq!(data.blocks[0], block(1, day, day + 100));
+
+        // Copying onto the same day overlaps the originals
+        assert!(data.copy_blocks(&[1], 0, OverlapPolicy::Reject).is_err());
+        assert_eq!(data.blocks.len(), 4);
+    }
+
+    #[test]
+    fn test_apply_recurrences_once_per_day() {
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let mut data = Data::new();
+        data.recurrences.push(Recurrence {
+            id: 1,
+            category: "standup".to_string(),
+            weekdays: vec![0, 1, 2, 3, 4],
+            start: "09:30".to_string(),
+            end: "09:45".to_string(),
+            note: None,
+            tags: vec![],
+            applied_until: time_utils::start_of_day(monday),
+        });
+        // Tuesday already has a planned block at that time
+        let tuesday = time_utils::start_of_day(monday + Days::new(1));
+        data.blocks = vec![block(1, tuesday, tuesday + 86_400_000)];
+        data.blocks[0].planned = true;
+
+        assert_eq!(data.apply_recurrences(monday + Days::new(6)), (4, 1));
+        assert_eq!(data.blocks.len(), 5);
+        assert_eq!(data.apply_recurrences(monday + Days::new(6)), (0, 0));
+    }
+
+    #[test]
+    fn test_template_blocks_keep_times_of_day() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+        let at = |date: NaiveDate, h: u32| {
+            time_utils::at_time(date, NaiveTime::from_hms_opt(h, 0, 0).unwrap())
+        };
+        let blocks = [
+            block(1, at(day, 9), at(day, 11)),
+            block(2, at(day, 23), at(day + Days::new(1), 1)),
+        ];
+        let template = Template::from_blocks("deep", &blocks.iter().collect::<Vec<_>>());
+
+        let next_day = day + Days::new(7);
+        let applied = template.blocks_on(next_day).unwrap();
+        assert_eq!(applied[0].start_unix, at(next_day, 9));
+        assert_eq!(applied[1].end_unix, Some(at(next_day + Days::new(1), 1)));
+        assert!(applied.iter().all(|b| b.planned));
+    }
+
+    #[test]
+    fn test_advance_queue_catches_up() {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let minute = 60_000;
+        let mut data = Data::new();
+        data.blocks = vec![block(1, now - 30 * minute, now - 20 * minute)];
+        data.queue = TimerQueue {
+            waiting: vec![
+                QueuedTimer {
+                    category: "break".to_string(),
+                    duration_ms: 10 * minute,
+                },
+                QueuedTimer {
+                    category: "code".to_string(),
+                    duration_ms: 50 * minute,
+                },
+                QueuedTimer {
+                    category: "review".to_string(),
+                    duration_ms: 30 * minute,
+                },
+            ],
+            current: Some(1),
+        };
+
+        // The break finished while zyr was not running, so code started right after it
+        let started = data.advance_queue();
+        assert_eq!(started.len(), 2);
+        assert_eq!(started[1].start_unix, now - 10 * minute);
+        assert_eq!(data.queue.current, Some(started[1].id));
+        assert_eq!(data.queue.waiting.len(), 1);
+        assert!(data.advance_queue().is_empty());
+    }
+
+    #[test]
+    fn test_shift_blocks_keeps_gaps() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 1000, 1100), block(3, 1200, 1300)];
+
+        data.shift_blocks(&[2, 3], 500, OverlapPolicy::Reject)
+            .unwrap();
+        let starts: Vec<u64> = data.blocks.iter().map(|b| b.start_unix).collect();
+        assert_eq!(starts, vec![0, 1500, 1700]);
+
+        // Moving block 2 onto block 1 is rejected without moving block 3 either
+        assert!(
+            data.shift_blocks(&[2, 3], -1450, OverlapPolicy::Reject)
+                .is_err()
+        );
+        assert_eq!(data.blocks[2].start_unix, 1700);
+        assert!(data.shift_blocks(&[1], -1, OverlapPolicy::Reject).is_err());
+    }
+
+    #[test]
+    fn test_trash_and_restore_block() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 100, 200)];
+
+        data.trash_block(0);
+        assert_eq!(data.blocks, vec![block(2, 100, 200)]);
+        // Trashed blocks keep their id, so it is not given to a new block
+        assert_eq!(data.next_block_id(), 3);
+
+        let restored = data.restore_block(1).unwrap();
+        assert_eq!(restored.id, 1);
+        assert_eq!(data.blocks, vec![block(1, 0, 100), block(2, 100, 200)]);
+        assert!(data.trash.is_empty());
+        assert!(data.restore_block(1).is_none());
+    }
+
+    #[test]
+    fn test_restore_block_with_taken_id() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100)];
+        data.trash_block(0);
+        data.blocks.push(block(1, 200, 300));
+
+        assert_eq!(data.restore_block(1).unwrap().id, 2);
+        assert_eq!(data.blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_purge_trash_keeps_recent_blocks() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 100, 200)];
+        data.trash_block(0);
+        data.trash_block(0);
+        data.trash[0].deleted_unix = 0;
+
+        assert_eq!(data.purge_trash(), 1);
+        assert_eq!(data.trash.len(), 1);
+        assert_eq!(data.trash[0].block.id, 2);
+    }
+
+    #[test]
+    fn test_running_block_index_not_last() {
+        let now = time_utils::since_unix().as_millis() as u64;
+        let mut data = Data::new();
+        data.blocks = vec![block(1, now - 5000, now - 4000)];
+        data.insert_block(TimeBlock {
+            end_unix: None,
+            ..block(2, now - 3000, 0)
+        });
+        data.insert_block(block(3, now + 10000, now + 20000));
+        data.insert_block(TimeBlock {
+            planned: true,
+            ..block(4, now - 1000, now + 1000)
+        });
+
+        let ids: Vec<u64> = data.blocks.iter().map(|b| b.id).collect();
+        assert_eq!(ids, vec![1, 2, 4, 3]);
+        assert_eq!(data.running_block_index(), Some(1));
+    }
+
+    #[test]
+    fn test_put_block_keeps_order_numbers() {
+        let mut data = Data::new();
+        data.blocks = vec![block(1, 0, 100), block(2, 200, 300)];
+
+        data.put_block(block(3, 100, 150), OverlapPolicy::Reject)
+            .unwrap();
+        assert_eq!(data.resolve_block(BlockRef::Order(0)), Ok(2));
+        assert_eq!(data.resolve_block(BlockRef::Order(1)), Ok(1));
+
+        // Moving a block changes its order number
+        data.put_block(block(1, 400, 500), OverlapPolicy::Reject)
+            .unwrap();
+        assert_eq!(
+            data.blocks[data.resolve_block(BlockRef::Order(0)).unwrap()].id,
+            1
+        );
+    }
+
+    #[test]
+    fn test_resolve_block_counts_planned_blocks_separately() {
+        // The planned block lies after the tracked one, as if it were still to come
+        let now = 10_000_000;
+        let mut data = Data::new();
+        data.blocks = vec![
+            block(1, now - 7_200_000, now - 3_600_000),
+            TimeBlock {
+                planned: true,
+                ..block(2, now + 3_600_000, now + 7_200_000)
+            },
+        ];
+
+        // The future planned block does not take the place of the finished tracked one
+        assert_eq!(data.resolve_block(BlockRef::Order(0)), Ok(0));
+        assert!(data.resolve_block(BlockRef::Order(1)).is_err());
+        assert_eq!(data.resolve_block(BlockRef::Planned(0)), Ok(1));
+        assert_eq!(data.resolve_block(BlockRef::Id(2)), Ok(1));
+    }
+
+    #[test]
+    fn test_block_ref_from_str() {
+        assert_eq!("#12".parse(), Ok(BlockRef::Id(12)));
+        assert_eq!("3".parse(), Ok(BlockRef::Order(3)));
+        assert!("#x".parse::<BlockRef>().is_err());
+        assert_eq!("p0".parse(), Ok(BlockRef::Planned(0)));
+        assert!("-1".parse::<BlockRef>().is_err());
+        assert!("p".parse::<BlockRef>().is_err());
+    }
+
+    #[test]
+    fn test_time_block_display_with_note() {
+        let mut tb = block(1, 0, 100);
+        assert!(!tb.to_string().contains('('));
+        tb.note = Some("review PR".to_string());
+        assert!(tb.to_string().ends_with(" (review PR)"));
+    }
+
+    #[test]
+    fn test_planned_blocks_overlap_only_planned_ones() {
+        let mut data = Data::new();
+        data.blocks = vec![TimeBlock {
+            planned: true,
+            ..block(1, 0, 100)
+        }];
+
+        // A tracked block may cover a planned one, but not another tracked one
+        data.put_block(block(2, 50, 150), OverlapPolicy::Reject)
+            .unwrap();
+        assert!(
+            data.put_block(block(3, 100, 200), OverlapPolicy::Reject)
+                .is_err()
+        );
+        assert!(
+            data.put_block(
+                TimeBlock {
+                    planned: true,
+                    ..block(3, 90, 120)
+                },
+                OverlapPolicy::Reject
+            )
+            .is_err()
+        );
+        // Only tracked time closes gaps
+        assert_eq!(data.gaps(0, 200), vec![(0, 50), (150, 200)]);
+    }
 }
@@ -1,27 +1,54 @@
 mod cli;
 mod domain;
+mod storage;
 mod terminal;
 mod utils;
 
+use chrono::Local;
 use clap::Parser;
 use cli::Cli;
-use domain::Data;
 use domain::Executable;
+use storage::Store;
 
 use crate::utils::file_utils;
 
 /// The starting point of the program
 fn main() {
-    let data_path = file_utils::get_data_path();
-    let mut data = Data::from_file(&data_path);
     let cli = Cli::parse();
+    let data_path = match file_utils::resolve_data_path(cli.data.as_deref(), cli.profile.as_deref())
+    {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Execution failed. {e}");
+            return;
+        }
+    };
+    let mut store = Store::new(data_path);
+    let mut data = match store.load() {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Could not load data. {e}");
+            return;
+        }
+    };
+    data.purge_trash();
+    data.apply_recurrences(Local::now().date_naive());
+    for block in data.advance_queue() {
+        println!("Started the queued timer {}", block.category);
+    }
+    if let Err(e) = store.commit_unrecorded(&mut data) {
+        eprintln!("Could not save data. {e}");
+        return;
+    }
 
-    let result = cli.command.execute(&mut data);
+    let result = cli.command.execute(&mut data, &mut store);
     if let Err(e) = result {
         eprintln!("Execution failed. {e}");
     }
 
-    data.save(&data_path);
+    if let Err(e) = store.commit(&mut data) {
+        eprintln!("Could not save data. {e}");
+    }
 }
 
 #[cfg(test)]
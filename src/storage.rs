@@ -0,0 +1,531 @@
+pub mod backups;
+pub mod history;
+pub mod journal;
+pub mod json;
+pub mod migrations;
+pub mod recovery;
+pub mod sqlite;
+
+use crate::domain::{Data, LoadError};
+use crate::utils::file_utils;
+use clap::ValueEnum;
+use history::History;
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A way of reading and writing the user's data.
+pub trait Storage {
+    /// Read the stored data, upgrading it to the current schema version if it is older.
+    ///
+    /// * return - The data and the schema version it was stored with, or an error
+    fn read(&self) -> Result<(Data, u32), LoadError>;
+
+    /// Replace the stored data with `data`.
+    ///
+    /// * `previous` - What is currently stored, so that only the differences have to be written.
+    ///   `None` rewrites everything.
+    fn write(&self, previous: Option<&Data>, data: &Data) -> Result<(), Box<dyn Error>>;
+
+    /// Try to get the data back after `read` failed. By default, the error is returned as is.
+    fn recover(&self, error: LoadError) -> Result<Data, Box<dyn Error>> {
+        Err(error.into())
+    }
+
+    /// Shrink the stored history of changes down to `data`, which is what is currently stored.
+    ///
+    /// * return - Whether this format supports compaction, or an error
+    fn compact(&self, _data: &Data) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+}
+
+/// The formats in which the user's data can be stored.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum StorageKind {
+    /// A single JSON file that is rewritten on every change
+    Json,
+    /// An SQLite database where only the changed time blocks are written
+    Sqlite,
+    /// An append-only journal of changes, which also serves as an audit trail
+    Journal,
+}
+
+impl StorageKind {
+    /// Pick the format from the extension of the data file. Unknown extensions are JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("sqlite" | "sqlite3" | "db") => Self::Sqlite,
+            Some("journal") => Self::Journal,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Sqlite => "sqlite",
+            Self::Journal => "journal",
+        }
+    }
+
+    pub fn open(self, path: PathBuf) -> Box<dyn Storage> {
+        match self {
+            Self::Json => Box::new(json::JsonStorage::new(path)),
+            Self::Sqlite => Box::new(sqlite::SqliteStorage::new(path)),
+            Self::Journal => Box::new(journal::JournalStorage::new(path)),
+        }
+    }
+}
+
+/// When the data file was last modified and how large it was.
+type Fingerprint = (SystemTime, u64);
+
+/// The place where the user's data is persisted for the current invocation of `zyr`.
+///
+/// Remembers the data as it was when loaded, so that changes made by other `zyr` processes in
+/// the meantime are detected and merged instead of being overwritten.
+pub struct Store {
+    path: PathBuf,
+    storage: Box<dyn Storage>,
+    base: Data,
+    /// The fingerprint of the data file when `base` was read or written, `None` if unknown.
+    /// As long as it does not change, the data file does not have to be read again.
+    seen: Option<Fingerprint>,
+}
+
+impl Store {
+    /// Use the data file at `path`, in the format given by its extension.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            storage: StorageKind::from_path(&path).open(path.clone()),
+            path,
+            base: Data::empty(),
+            seen: None,
+        }
+    }
+
+    /// The path of the data file this store reads from and writes to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the data file, offering to recover it if it is missing or corrupted.
+    pub fn load(&mut self) -> Result<Data, Box<dyn Error>> {
+        // Taken before reading, so a write by another process in between is not missed
+        let mut seen = self.fingerprint();
+        let mut data = match self.read() {
+            Ok((data, version)) if version < migrations::CURRENT_VERSION => {
+                self.upgrade(&data, version)?;
+                seen = None;
+                data
+            }
+            Ok((data, _)) => data,
+            Err(e) => {
+                seen = None;
+                self.storage.recover(e)?
+            }
+        };
+        data.sort_blocks();
+        self.base = data.clone();
+        self.seen = seen;
+        Ok(data)
+    }
+
+    /// Return the current fingerprint of the data file, or `None` if it cannot be read.
+    fn fingerprint(&self) -> Option<Fingerprint> {
+        let metadata = fs::metadata(&self.path).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+
+    /// Read the stored data with the time blocks in chronological order.
+    fn read(&self) -> Result<(Data, u32), LoadError> {
+        let (mut data, version) = self.storage.read()?;
+        data.sort_blocks();
+        Ok((data, version))
+    }
+
+    /// Write data that was migrated from an older schema version back to the data file,
+    /// keeping a copy of the file as it was before the migration.
+    fn upgrade(&self, data: &Data, from_version: u32) -> Result<(), Box<dyn Error>> {
+        let _lock = file_utils::lock(&self.path)?;
+        let backup_path = file_utils::with_suffix(&self.path, &format!(".v{from_version}.bak"));
+        fs::copy(&self.path, &backup_path)?;
+        self.storage.write(None, data)?;
+        eprintln!(
+            "Upgraded the data file from schema version {from_version} to {}. The old file was kept at {}",
+            migrations::CURRENT_VERSION,
+            backup_path.display()
+        );
+        Ok(())
+    }
+
+    /// Persist the changes made to `data` since it was loaded or last committed.
+    ///
+    /// The data file is locked for the duration of the read-modify-write cycle. If another
+    /// process changed the file in the meantime, both sets of changes are merged and `data` is
+    /// updated to the merged result. Conflicting changes are not written and cause an error.
+    ///
+    /// A snapshot of the previous version is added to the backups before writing, and the
+    /// changes are recorded in the history, so they can be undone.
+    pub fn commit(&mut self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        self.save(data, true)
+    }
+
+    /// Persist changes that `zyr` makes on its own, like purging the trash, without recording
+    /// them in the history. Otherwise undoing the next command would revert them instead.
+    pub fn commit_unrecorded(&mut self, data: &mut Data) -> Result<(), Box<dyn Error>> {
+        self.save(data, false)
+    }
+
+    fn save(&mut self, data: &mut Data, record: bool) -> Result<(), Box<dyn Error>> {
+        if *data == self.base {
+            return Ok(());
+        }
+
+        let _lock = file_utils::lock(&self.path)?;
+        let mut history = record.then(|| History::load(&self.path));
+        if let Some(history) = &mut history {
+            history.record(&self.base, data);
+        }
+
+        // Reading the whole store is only needed if another process wrote to it
+        let current = if self.seen.is_some() && self.fingerprint() == self.seen {
+            self.base.clone()
+        } else {
+            self.read()?.0
+        };
+        if current != self.base {
+            *data = merge(&self.base, data, &current).ok_or(
+                "The data file was modified by another zyr process and the changes conflict with this command. Nothing was saved.",
+            )?;
+        }
+
+        self.write(&current, data)?;
+        if let Some(history) = history {
+            history.save(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Revert the last `steps` changes recorded in the history of the data file.
+    ///
+    /// * return - The undone changes, most recent first, or an error
+    pub fn undo(
+        &mut self,
+        data: &mut Data,
+        steps: usize,
+    ) -> Result<Vec<history::Entry>, Box<dyn Error>> {
+        self.replay(data, |history, current| history.undo(current, steps))
+    }
+
+    /// Apply the last `steps` undone changes again.
+    ///
+    /// * return - The redone changes, oldest first, or an error
+    pub fn redo(
+        &mut self,
+        data: &mut Data,
+        steps: usize,
+    ) -> Result<Vec<history::Entry>, Box<dyn Error>> {
+        self.replay(data, |history, current| history.redo(current, steps))
+    }
+
+    /// Change the data with `step` and write it without recording the change in the history.
+    fn replay(
+        &mut self,
+        data: &mut Data,
+        step: impl FnOnce(&mut History, &Data) -> Result<(Data, Vec<history::Entry>), String>,
+    ) -> Result<Vec<history::Entry>, Box<dyn Error>> {
+        self.commit_unrecorded(data)?;
+        let _lock = file_utils::lock(&self.path)?;
+        let (current, _) = self.read()?;
+        let mut history = History::load(&self.path);
+        let (mut changed, entries) = step(&mut history, &current)?;
+        changed.sort_blocks();
+
+        self.write(&current, &changed)?;
+        history.save(&self.path)?;
+        *data = changed;
+        Ok(entries)
+    }
+
+    /// Take the automatic backups and write `data` over `current`, which is what is stored.
+    /// The data file has to be locked.
+    fn write(&mut self, current: &Data, data: &Data) -> Result<(), Box<dyn Error>> {
+        backups::take_automatic(&self.path, current, &data.settings)?;
+        self.storage.write(Some(current), data)?;
+        self.base = data.clone();
+        self.seen = self.fingerprint();
+        Ok(())
+    }
+
+    /// Compact the stored history of changes, if the storage format keeps one.
+    ///
+    /// * return - Whether the storage format supports compaction, or an error
+    pub fn compact(&mut self, data: &mut Data) -> Result<bool, Box<dyn Error>> {
+        self.commit(data)?;
+        let _lock = file_utils::lock(&self.path)?;
+        let (current, _) = self.read()?;
+        self.storage.compact(&current)
+    }
+
+    /// Move the data to a new data file in another format, next to the current one.
+    ///
+    /// The old data file is renamed with a `.migrated` suffix, so that profiles use the new one.
+    ///
+    /// * return - The path of the new data file, or an error
+    pub fn migrate_to(
+        &mut self,
+        kind: StorageKind,
+        data: &Data,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        if StorageKind::from_path(&self.path) == kind {
+            return Err(format!("The data is already stored as {}", kind.extension()).into());
+        }
+        let new_path = self.path.with_extension(kind.extension());
+        if new_path.exists() {
+            return Err(format!("{} already exists", new_path.display()).into());
+        }
+
+        let _lock = file_utils::lock(&self.path)?;
+        let storage = kind.open(new_path.clone());
+        storage.write(None, data)?;
+        fs::rename(&self.path, file_utils::with_suffix(&self.path, ".migrated"))?;
+
+        self.path.clone_from(&new_path);
+        self.storage = storage;
+        self.base = data.clone();
+        self.seen = self.fingerprint();
+        Ok(new_path)
+    }
+}
+
+/// Three-way merge of the changes made in `ours` and `theirs`, which both started from `base`.
+///
+/// * return - The merged data, or `None` if both sides changed the same value differently
+pub fn merge(base: &Data, ours: &Data, theirs: &Data) -> Option<Data> {
+    let merged = merge_value(
+        &serde_json::to_value(base).ok()?,
+        &serde_json::to_value(ours).ok()?,
+        &serde_json::to_value(theirs).ok()?,
+    )?;
+    let mut merged: Data = serde_json::from_value(merged).ok()?;
+    merged.fix_block_ids();
+    merged.sort_blocks();
+    Some(merged)
+}
+
+fn merge_value(base: &Value, ours: &Value, theirs: &Value) -> Option<Value> {
+    match (base, ours, theirs) {
+        (Value::Object(b), Value::Object(o), Value::Object(t)) => {
+            let mut merged = Map::new();
+            for key in b.keys().chain(o.keys()).chain(t.keys()) {
+                if merged.contains_key(key) {
+                    continue;
+                }
+                let (bv, ov, tv) = (
+                    b.get(key).unwrap_or(&Value::Null),
+                    o.get(key).unwrap_or(&Value::Null),
+                    t.get(key).unwrap_or(&Value::Null),
+                );
+                merged.insert(key.clone(), merge_value(bv, ov, tv)?);
+            }
+            Some(Value::Object(merged))
+        }
+        (Value::Array(b), Value::Array(o), Value::Array(t)) => {
+            merge_list(b, o, t).map(Value::Array)
+        }
+        _ if ours == base || theirs == ours => Some(theirs.clone()),
+        _ if theirs == base => Some(ours.clone()),
+        _ => None,
+    }
+}
+
+/// Apply the elements removed, replaced and appended in `ours` to `theirs`.
+///
+/// An element that `ours` removed or replaced must still exist in `theirs`, otherwise both
+/// sides touched the same element and the merge fails.
+fn merge_list(base: &[Value], ours: &[Value], theirs: &[Value]) -> Option<Vec<Value>> {
+    let removed = multiset_difference(base, ours);
+    let added = multiset_difference(ours, base);
+    let mut merged = theirs.to_vec();
+
+    for (i, old) in removed.iter().enumerate() {
+        let pos = merged.iter().position(|v| v == *old)?;
+        match added.get(i) {
+            Some(new) => merged[pos] = (*new).clone(),
+            None => {
+                merged.remove(pos);
+            }
+        }
+    }
+    merged.extend(added.iter().skip(removed.len()).map(|v| (*v).clone()));
+    Some(merged)
+}
+
+/// The elements of `a` that are not in `b`, counting duplicates, in the order of `a`.
+pub fn multiset_difference<'a, T: PartialEq>(a: &'a [T], b: &[T]) -> Vec<&'a T> {
+    let mut remaining: Vec<&T> = b.iter().collect();
+    a.iter()
+        .filter(|v| {
+            if let Some(pos) = remaining.iter().position(|r| r == v) {
+                remaining.swap_remove(pos);
+                false
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Recurrence, TimeBlock, TrashedBlock};
+    use crate::utils::time_utils;
+    use chrono::{Days, Local};
+
+    fn block(start_unix: u64, category: &str) -> TimeBlock {
+        TimeBlock {
+            id: start_unix + 1,
+            start_unix,
+            end_unix: Some(start_unix + 1000),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    fn data(blocks: Vec<TimeBlock>) -> Data {
+        let mut data = Data::new();
+        data.blocks = blocks;
+        data
+    }
+
+    #[test]
+    fn test_merge_independent_additions() {
+        let base = data(vec![block(0, "code")]);
+        let ours = data(vec![block(0, "code"), block(10, "study")]);
+        let theirs = data(vec![block(0, "code"), block(20, "break")]);
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(
+            merged.blocks,
+            vec![block(0, "code"), block(10, "study"), block(20, "break")]
+        );
+    }
+
+    #[test]
+    fn test_merge_edit_and_delete_of_different_blocks() {
+        let base = data(vec![block(0, "code"), block(10, "study")]);
+        let ours = data(vec![block(0, "review"), block(10, "study")]);
+        let theirs = data(vec![block(0, "code")]);
+
+        let merged = merge(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged.blocks, vec![block(0, "review")]);
+    }
+
+    #[test]
+    fn test_merge_conflicting_edits() {
+        let base = data(vec![block(0, "code")]);
+        let ours = data(vec![block(0, "review")]);
+        let theirs = data(vec![block(0, "study")]);
+
+        assert!(merge(&base, &ours, &theirs).is_none());
+    }
+
+    #[test]
+    fn test_commit_merges_changes_of_another_store() {
+        let dir = std::env::temp_dir().join(format!("zyr-store-merge-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        data(vec![block(0, "code")]).save(&path).unwrap();
+
+        let mut first = Store::new(path.clone());
+        let mut ours = first.load().unwrap();
+        let mut second = Store::new(path.clone());
+        let mut theirs = second.load().unwrap();
+
+        theirs.blocks.push(block(20, "break"));
+        second.commit(&mut theirs).unwrap();
+        ours.blocks.push(block(10, "study"));
+        first.commit(&mut ours).unwrap();
+
+        let stored = Data::from_file(&path).unwrap();
+        assert_eq!(
+            stored.blocks,
+            vec![block(0, "code"), block(10, "study"), block(20, "break")]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Load the data like `main` does, including the changes `zyr` makes on every start.
+    fn start(path: &Path) -> (Store, Data) {
+        let mut store = Store::new(path.to_path_buf());
+        let mut data = store.load().unwrap();
+        data.purge_trash();
+        data.apply_recurrences(Local::now().date_naive());
+        store.commit_unrecorded(&mut data).unwrap();
+        (store, data)
+    }
+
+    #[test]
+    fn test_undo_ignores_startup_changes() {
+        let dir = std::env::temp_dir().join(format!("zyr-store-undo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        let mut initial = data(vec![block(0, "code"), block(10, "study")]);
+        initial.trash.push(TrashedBlock {
+            block: block(20, "break"),
+            deleted_unix: 0,
+        });
+        let yesterday = Local::now().date_naive() - Days::new(1);
+        initial.recurrences.push(Recurrence {
+            id: 1,
+            category: "standup".to_string(),
+            weekdays: (0..7).collect(),
+            start: "09:30".to_string(),
+            end: "09:45".to_string(),
+            note: None,
+            tags: vec![],
+            applied_until: time_utils::start_of_day(yesterday),
+        });
+        initial.save(&path).unwrap();
+
+        // zyr plan del
+        let (mut store, mut data) = start(&path);
+        assert!(data.trash.is_empty());
+        let index = data.blocks.iter().position(|b| b.category == "study");
+        data.trash_block(index.unwrap());
+        store.commit(&mut data).unwrap();
+
+        // zyr undo
+        let (mut store, mut data) = start(&path);
+        store.undo(&mut data, 1).unwrap();
+        assert!(data.blocks.contains(&block(10, "study")));
+        assert_eq!(data.blocks.len(), 4);
+        assert!(data.trash.is_empty());
+        assert!(History::load(&path).applied().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_newer_file_keeps_it_untouched() {
+        let dir = std::env::temp_dir().join(format!("zyr-store-newer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        let text = format!(
+            r#"{{"schema_version":{},"blocks":[],"future_field":[1,2]}}"#,
+            migrations::CURRENT_VERSION + 1
+        );
+        fs::write(&path, &text).unwrap();
+
+        let mut store = Store::new(path.clone());
+        assert!(store.load().is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use crate::domain::{Data, LoadError, Settings};
+use crate::utils::time_utils;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The format of the timestamp at the start of every snapshot id.
+const ID_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// Why a snapshot was taken, which also decides how long it is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotKind {
+    /// The first snapshot taken on a day, kept for `Settings::backup_days` days
+    Daily,
+    /// Taken before every change, the last `Settings::backup_changes` are kept
+    Change,
+    /// Taken with `zyr backup create`, never removed automatically
+    Manual,
+    /// Taken before `zyr clear`, kept for `Settings::backup_days` days
+    Clear,
+}
+
+impl SnapshotKind {
+    const ALL: [Self; 4] = [Self::Daily, Self::Change, Self::Manual, Self::Clear];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Change => "change",
+            Self::Manual => "manual",
+            Self::Clear => "clear",
+        }
+    }
+}
+
+impl fmt::Display for SnapshotKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// A copy of the user's data stored in the backups directory.
+pub struct Snapshot {
+    /// The file name without extension, e.g. `20250131T093000-daily`
+    pub id: String,
+    pub kind: SnapshotKind,
+    pub created: DateTime<Local>,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    /// Parse the file name of a snapshot. Returns `None` for unrelated files.
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if path.extension()? != "json" {
+            return None;
+        }
+        let id = path.file_stem()?.to_str()?.to_string();
+        let (timestamp, rest) = id.split_once('-')?;
+        let kind_name = rest.split('-').next()?;
+        let kind = SnapshotKind::ALL
+            .into_iter()
+            .find(|k| k.name() == kind_name)?;
+        let naive = NaiveDateTime::parse_from_str(timestamp, ID_FORMAT).ok()?;
+        let created = Local.from_local_datetime(&naive).earliest()?;
+
+        Some(Self {
+            id,
+            kind,
+            created,
+            path,
+        })
+    }
+
+    pub fn load(&self) -> Result<Data, LoadError> {
+        Data::from_file(&self.path)
+    }
+}
+
+/// Return the directory holding the snapshots of a data file, e.g. `data.backups`.
+///
+/// The extension of the data file is left out, so the snapshots stay with the data when it is
+/// moved to another storage format.
+pub fn get_backups_dir(data_path: &Path) -> PathBuf {
+    data_path.with_extension("backups")
+}
+
+/// Return all snapshots of a data file, oldest first.
+pub fn list(data_path: &Path) -> Vec<Snapshot> {
+    let mut snapshots: Vec<Snapshot> = fs::read_dir(get_backups_dir(data_path))
+        .map(|entries| {
+            entries
+                .filter_map(|e| Snapshot::from_path(e.ok()?.path()))
+                .collect()
+        })
+        .unwrap_or_default();
+    // Ids only have a precision of seconds, so snapshots of the same second are ordered by when
+    // their files were written
+    snapshots.sort_by_cached_key(|s| {
+        let written = fs::metadata(&s.path).and_then(|m| m.modified()).ok();
+        (s.created, written)
+    });
+    snapshots
+}
+
+/// Find a snapshot by its id.
+pub fn find(data_path: &Path, id: &str) -> Option<Snapshot> {
+    list(data_path).into_iter().find(|s| s.id == id)
+}
+
+/// Write a snapshot of `data` to the backups directory.
+pub fn create(data_path: &Path, data: &Data, kind: SnapshotKind) -> io::Result<Snapshot> {
+    let dir = get_backups_dir(data_path);
+    fs::create_dir_all(&dir)?;
+
+    let timestamp = Local::now().format(ID_FORMAT);
+    let mut id = format!("{timestamp}-{kind}");
+    let mut n = 2;
+    while dir.join(format!("{id}.json")).exists() {
+        id = format!("{timestamp}-{kind}-{n}");
+        n += 1;
+    }
+
+    let path = dir.join(format!("{id}.json"));
+    let stringified = serde_json::to_string(data).expect("Object could not be serialized");
+    fs::write(&path, stringified)?;
+    Ok(Snapshot::from_path(path).expect("Snapshot names are always valid"))
+}
+
+/// Take the snapshots that are due before a change to the data file, then remove the ones that
+/// are older than the retention configured in `settings`.
+///
+/// * `data_path` - The path of the data file
+/// * `previous`  - The data as it is on disk, before the change
+/// * `settings`  - The settings that apply after the change
+pub fn take_automatic(data_path: &Path, previous: &Data, settings: &Settings) -> io::Result<()> {
+    let snapshots = list(data_path);
+    let now = Local::now();
+    let has_daily = snapshots
+        .iter()
+        .any(|s| s.kind == SnapshotKind::Daily && time_utils::same_day(s.created, now));
+
+    // The first snapshot of a day doubles as the change snapshot
+    let kind = if has_daily {
+        SnapshotKind::Change
+    } else {
+        SnapshotKind::Daily
+    };
+    create(data_path, previous, kind)?;
+    rotate(data_path, settings)
+}
+
+/// Remove the snapshots that are no longer covered by the retention settings.
+pub fn rotate(data_path: &Path, settings: &Settings) -> io::Result<()> {
+    let snapshots = list(data_path);
+    let oldest_kept = Local::now() - chrono::Duration::days(i64::from(settings.backup_days));
+    let changes = snapshots
+        .iter()
+        .filter(|s| s.kind == SnapshotKind::Change)
+        .count();
+    let mut changes_to_remove = changes.saturating_sub(settings.backup_changes as usize);
+
+    for snapshot in &snapshots {
+        let expired = match snapshot.kind {
+            SnapshotKind::Daily | SnapshotKind::Clear => snapshot.created < oldest_kept,
+            SnapshotKind::Change if changes_to_remove > 0 => {
+                changes_to_remove -= 1;
+                true
+            }
+            SnapshotKind::Change | SnapshotKind::Manual => false,
+        };
+        if expired {
+            fs::remove_file(&snapshot.path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_data_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zyr-backups-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("data.json")
+    }
+
+    #[test]
+    fn test_snapshot_from_path() {
+        let snapshot = Snapshot::from_path(PathBuf::from("20250131T093000-change-2.json")).unwrap();
+        assert_eq!(snapshot.id, "20250131T093000-change-2");
+        assert_eq!(snapshot.kind, SnapshotKind::Change);
+        assert!(Snapshot::from_path(PathBuf::from("20250131T093000-other.json")).is_none());
+        assert!(Snapshot::from_path(PathBuf::from("20250131T093000-daily.txt")).is_none());
+    }
+
+    #[test]
+    fn test_rotate_keeps_last_changes_and_manual() {
+        let path = temp_data_path("rotate");
+        let data = Data::new();
+        create(&path, &data, SnapshotKind::Manual).unwrap();
+        for _ in 0..3 {
+            create(&path, &data, SnapshotKind::Change).unwrap();
+        }
+        let settings = Settings {
+            backup_changes: 1,
+            ..Settings::default()
+        };
+
+        rotate(&path, &settings).unwrap();
+        let kinds: Vec<SnapshotKind> = list(&path).into_iter().map(|s| s.kind).collect();
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.contains(&SnapshotKind::Manual));
+        assert!(kinds.contains(&SnapshotKind::Change));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_take_automatic_keeps_quick_changes() {
+        let path = temp_data_path("automatic");
+        let settings = Settings::default();
+        let first = Data::new();
+        let mut second = Data::new();
+        second.settings.backup_days = 3;
+
+        take_automatic(&path, &first, &settings).unwrap();
+        take_automatic(&path, &second, &settings).unwrap();
+
+        let snapshots = list(&path);
+        let kinds: Vec<SnapshotKind> = snapshots.iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, vec![SnapshotKind::Daily, SnapshotKind::Change]);
+        assert_eq!(snapshots[0].load().unwrap(), first);
+        assert_eq!(snapshots[1].load().unwrap(), second);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+use crate::domain::Data;
+use crate::utils::{file_utils, time_utils};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many changes are kept. Older ones can no longer be undone.
+const HISTORY_LIMIT: usize = 100;
+
+/// One difference between two versions of the data.
+///
+/// Blocks and values are kept as plain JSON, like in the journal, so the history stays readable
+/// after the schema changes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "change", rename_all = "snake_case")]
+enum Change {
+    /// A block was added (`before` is `None`), deleted (`after` is `None`) or edited
+    Block {
+        id: u64,
+        before: Option<Value>,
+        after: Option<Value>,
+    },
+    /// Any field of `Data` other than the blocks changed, e.g. the settings
+    Field {
+        key: String,
+        before: Option<Value>,
+        after: Option<Value>,
+    },
+}
+
+impl Change {
+    /// Swap `before` and `after`, so applying the change reverts it.
+    fn inverted(&self) -> Self {
+        match self.clone() {
+            Self::Block { id, before, after } => Self::Block {
+                id,
+                before: after,
+                after: before,
+            },
+            Self::Field { key, before, after } => Self::Field {
+                key,
+                before: after,
+                after: before,
+            },
+        }
+    }
+}
+
+/// The changes one command made to the data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Milliseconds since the Unix epoch
+    pub at: u64,
+    /// The command line that made the changes, e.g. `zyr plan del 3`
+    pub command: String,
+    changes: Vec<Change>,
+}
+
+impl Entry {
+    /// Describe the changes in a few words, e.g. `1 block added, 2 blocks edited`.
+    pub fn summary(&self) -> String {
+        let (mut added, mut edited, mut deleted, mut fields) = (0, 0, 0, vec![]);
+        for change in &self.changes {
+            match change {
+                Change::Block { before: None, .. } => added += 1,
+                Change::Block { after: None, .. } => deleted += 1,
+                Change::Block { .. } => edited += 1,
+                Change::Field { key, .. } => fields.push(format!("{key} changed")),
+            }
+        }
+
+        let parts: Vec<String> = [(added, "added"), (edited, "edited"), (deleted, "deleted")]
+            .into_iter()
+            .filter(|(n, _)| *n > 0)
+            .map(|(n, what)| format!("{n} block{} {what}", if n == 1 { "" } else { "s" }))
+            .chain(fields)
+            .collect();
+        parts.join(", ")
+    }
+}
+
+/// The most recent changes to a data file, stored next to it, e.g. in `data.history.json`.
+///
+/// Undoing a change moves it to the end of the list of undone changes, from where it can be
+/// redone. Any new change discards the undone ones.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct History {
+    /// Oldest first
+    entries: Vec<Entry>,
+    /// How many of the last entries have been undone
+    undone: usize,
+}
+
+impl History {
+    /// Read the history of a data file. A missing or unreadable history starts out empty.
+    pub fn load(data_path: &Path) -> Self {
+        fs::read_to_string(get_history_path(data_path))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, data_path: &Path) -> io::Result<()> {
+        let stringified = serde_json::to_string(self).expect("Object could not be serialized");
+        file_utils::write_atomic(&get_history_path(data_path), stringified.as_bytes())
+    }
+
+    /// The changes that are currently applied, oldest first.
+    pub fn applied(&self) -> &[Entry] {
+        &self.entries[..self.entries.len() - self.undone]
+    }
+
+    /// The changes that were undone and can be redone, the next one to redo first.
+    pub fn undone(&self) -> &[Entry] {
+        &self.entries[self.entries.len() - self.undone..]
+    }
+
+    /// Remember the changes a command made to the data.
+    ///
+    /// * `before` - The data before the command ran
+    /// * `after`  - The data after the command ran
+    pub fn record(&mut self, before: &Data, after: &Data) {
+        let changes = diff(before, after);
+        if changes.is_empty() {
+            return;
+        }
+
+        self.entries.truncate(self.entries.len() - self.undone);
+        self.undone = 0;
+        self.entries.push(Entry {
+            at: time_utils::since_unix().as_millis() as u64,
+            command: current_command(),
+            changes,
+        });
+        let excess = self.entries.len().saturating_sub(HISTORY_LIMIT);
+        self.entries.drain(..excess);
+    }
+
+    /// Revert the last `steps` changes that are applied.
+    ///
+    /// * return - The reverted data and the undone entries, most recent first, or an error if
+    ///   there is nothing to undo or the data changed in a way that conflicts
+    pub fn undo(&mut self, data: &Data, steps: usize) -> Result<(Data, Vec<Entry>), String> {
+        let applied = self.entries.len() - self.undone;
+        if applied == 0 {
+            return Err("There is nothing to undo".to_string());
+        }
+
+        let mut value = serde_json::to_value(data).expect("Object could not be serialized");
+        let mut entries = vec![];
+        for entry in self.entries[applied.saturating_sub(steps)..applied]
+            .iter()
+            .rev()
+        {
+            for change in entry.changes.iter().rev() {
+                apply(&mut value, &change.inverted()).map_err(|e| conflict("undo", entry, &e))?;
+            }
+            entries.push(entry.clone());
+        }
+
+        self.undone += entries.len();
+        Ok((from_value(value)?, entries))
+    }
+
+    /// Apply the last `steps` undone changes again.
+    ///
+    /// * return - The changed data and the redone entries, oldest first, or an error if there is
+    ///   nothing to redo or the data changed in a way that conflicts
+    pub fn redo(&mut self, data: &Data, steps: usize) -> Result<(Data, Vec<Entry>), String> {
+        if self.undone == 0 {
+            return Err("There is nothing to redo".to_string());
+        }
+
+        let mut value = serde_json::to_value(data).expect("Object could not be serialized");
+        let mut entries = vec![];
+        for entry in self.undone().iter().take(steps) {
+            for change in &entry.changes {
+                apply(&mut value, change).map_err(|e| conflict("redo", entry, &e))?;
+            }
+            entries.push(entry.clone());
+        }
+
+        self.undone -= entries.len();
+        Ok((from_value(value)?, entries))
+    }
+}
+
+/// Return the path of the history of a data file, e.g. `data.history.json`.
+///
+/// Like the backups, the history stays with the data when it is moved to another storage format.
+pub fn get_history_path(data_path: &Path) -> PathBuf {
+    data_path.with_extension("history.json")
+}
+
+/// The command line `zyr` was started with, quoting arguments that contain spaces.
+fn current_command() -> String {
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .map(|a| {
+            if a.contains(char::is_whitespace) {
+                format!("\"{a}\"")
+            } else {
+                a
+            }
+        })
+        .collect();
+    format!("zyr {}", args.join(" "))
+}
+
+fn conflict(action: &str, entry: &Entry, reason: &str) -> String {
+    format!(
+        "Cannot {action} `{}`, because {reason}. Nothing was changed.",
+        entry.command
+    )
+}
+
+fn from_value(value: Value) -> Result<Data, String> {
+    serde_json::from_value(value).map_err(|e| format!("The reverted data is invalid: {e}"))
+}
+
+/// Describe the changes from `before` to `after`.
+fn diff(before: &Data, after: &Data) -> Vec<Change> {
+    let (Value::Object(mut old), Value::Object(mut new)) = (
+        serde_json::to_value(before).expect("Object could not be serialized"),
+        serde_json::to_value(after).expect("Object could not be serialized"),
+    ) else {
+        unreachable!("Data is always serialized as an object");
+    };
+    let old_blocks = blocks_by_id(old.remove("blocks"));
+    let new_blocks = blocks_by_id(new.remove("blocks"));
+    let mut changes = vec![];
+
+    for key in old
+        .keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)))
+    {
+        if old.get(key) != new.get(key) {
+            changes.push(Change::Field {
+                key: key.clone(),
+                before: old.get(key).cloned(),
+                after: new.get(key).cloned(),
+            });
+        }
+    }
+    for block in &before.blocks {
+        if old_blocks.get(&block.id) != new_blocks.get(&block.id) {
+            changes.push(Change::Block {
+                id: block.id,
+                before: old_blocks.get(&block.id).cloned(),
+                after: new_blocks.get(&block.id).cloned(),
+            });
+        }
+    }
+    for block in after
+        .blocks
+        .iter()
+        .filter(|b| !old_blocks.contains_key(&b.id))
+    {
+        changes.push(Change::Block {
+            id: block.id,
+            before: None,
+            after: new_blocks.get(&block.id).cloned(),
+        });
+    }
+    changes
+}
+
+fn blocks_by_id(blocks: Option<Value>) -> HashMap<u64, Value> {
+    match blocks {
+        Some(Value::Array(blocks)) => blocks
+            .into_iter()
+            .map(|b| (b.get("id").and_then(Value::as_u64).unwrap_or(0), b))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Apply a change to the JSON value of the data, checking that the data still matches what the
+/// change expects to find.
+///
+/// * return - Nothing, or why the change cannot be applied
+fn apply(value: &mut Value, change: &Change) -> Result<(), String> {
+    let Value::Object(fields) = value else {
+        unreachable!("Data is always serialized as an object");
+    };
+
+    match change {
+        Change::Field { key, before, after } => {
+            if fields.get(key) != before.as_ref() {
+                return Err(format!("`{key}` was changed since"));
+            }
+            match after {
+                Some(after) => fields.insert(key.clone(), after.clone()),
+                None => fields.remove(key),
+            };
+        }
+        Change::Block { id, before, after } => {
+            let blocks = blocks_mut(fields);
+            let id_of = |b: &Value| b.get("id").and_then(Value::as_u64);
+            let pos = blocks.iter().position(|b| id_of(b) == Some(*id));
+            if pos.map(|p| &blocks[p]) != before.as_ref() {
+                return Err(format!("the time block with id {id} was changed since"));
+            }
+            match (pos, after) {
+                (Some(pos), Some(after)) => blocks[pos] = after.clone(),
+                (Some(pos), None) => {
+                    blocks.remove(pos);
+                }
+                // Keep the blocks in chronological order
+                (None, Some(after)) => {
+                    let start_of = |b: &Value| b.get("start_unix").and_then(Value::as_u64);
+                    let pos = blocks.partition_point(|b| start_of(b) <= start_of(after));
+                    blocks.insert(pos, after.clone());
+                }
+                (None, None) => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn blocks_mut(fields: &mut Map<String, Value>) -> &mut Vec<Value> {
+    let blocks = fields
+        .entry("blocks")
+        .or_insert_with(|| Value::Array(vec![]));
+    if !blocks.is_array() {
+        *blocks = Value::Array(vec![]);
+    }
+    blocks
+        .as_array_mut()
+        .expect("Blocks were just made an array")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TimeBlock;
+
+    fn block(id: u64, category: &str) -> TimeBlock {
+        TimeBlock {
+            id,
+            start_unix: id * 1000,
+            end_unix: Some(id * 1000 + 500),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    #[test]
+    fn test_history_undo_and_redo() {
+        let mut first = Data::new();
+        first.blocks = vec![block(1, "code"), block(2, "study")];
+        let mut second = first.clone();
+        second.blocks.remove(0);
+        second.blocks[0].category = "review".to_string();
+        second.settings.backup_days = 3;
+        let mut third = second.clone();
+        third.blocks.push(block(3, "break"));
+
+        let mut history = History::default();
+        history.record(&first, &second);
+        history.record(&second, &third);
+
+        let (undone, entries) = history.undo(&third, 5).unwrap();
+        assert_eq!(undone, first);
+        assert_eq!(entries.len(), 2);
+        assert!(history.applied().is_empty());
+
+        let (redone, _) = history.redo(&undone, 1).unwrap();
+        assert_eq!(redone, second);
+        assert_eq!(history.applied().len(), 1);
+
+        // A new change discards what is left to redo
+        history.record(&second, &first);
+        assert!(history.undone().is_empty());
+        assert!(history.redo(&first, 1).is_err());
+    }
+
+    #[test]
+    fn test_history_undo_conflict() {
+        let first = Data::new();
+        let mut second = first.clone();
+        second.blocks.push(block(1, "code"));
+        let mut history = History::default();
+        history.record(&first, &second);
+
+        let mut edited = second.clone();
+        edited.blocks[0].category = "study".to_string();
+        assert!(history.undo(&edited, 1).is_err());
+        assert_eq!(history.applied().len(), 1);
+    }
+}
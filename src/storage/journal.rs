@@ -0,0 +1,377 @@
+use super::{Storage, migrations};
+use crate::domain::{Data, LoadError};
+use crate::utils::{file_utils, time_utils};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// After how many events the journal is compacted into a single snapshot.
+const COMPACT_AFTER: usize = 500;
+
+/// A single change to the user's data, stored as one line of the journal.
+///
+/// Blocks and values are kept as plain JSON, so that a journal can be replayed and migrated as
+/// a whole, no matter which version of `zyr` wrote it.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event {
+    /// The complete data, which the events after it are applied to
+    Snapshot {
+        data: Value,
+    },
+    BlockAdded {
+        block: Value,
+    },
+    BlockEdited {
+        block: Value,
+    },
+    BlockDeleted {
+        id: u64,
+    },
+    /// Any field of `Data` other than the blocks changed, e.g. the settings
+    FieldChanged {
+        key: String,
+        value: Value,
+    },
+}
+
+/// An event and the moment it was recorded.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Entry {
+    /// Milliseconds since the Unix epoch
+    at: u64,
+    #[serde(flatten)]
+    event: Event,
+}
+
+/// Stores every change to the data as a line in an append-only journal, and rebuilds the data
+/// by replaying it.
+///
+/// Once the journal grows past `COMPACT_AFTER` events, it is replaced with a single snapshot and
+/// the old events are moved to the `.archive` file next to it, which keeps the full history.
+pub struct JournalStorage {
+    path: PathBuf,
+    /// How many lines the journal has, once it was read or written by this process
+    lines: Cell<Option<usize>>,
+}
+
+impl JournalStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lines: Cell::new(None),
+        }
+    }
+
+    /// Read the journal, ignoring a last line that was cut off by a crash while appending.
+    fn read_entries(&self) -> Result<Vec<Entry>, LoadError> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.lines.set(Some(0));
+                return Ok(vec![]);
+            }
+            Err(e) => return Err(LoadError::Unreadable(e)),
+        };
+        self.lines
+            .set(Some(text.bytes().filter(|&b| b == b'\n').count()));
+
+        let complete = text.rfind('\n').map_or("", |i| &text[..i]);
+        complete
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    LoadError::Unreadable(io::Error::other(format!(
+                        "line {} of the journal is corrupted: {e}",
+                        i + 1
+                    )))
+                })
+            })
+            .collect()
+    }
+
+    /// Append lines to the journal and flush them to disk.
+    fn append(&self, entries: &[Entry]) -> io::Result<()> {
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+
+        // Drop a line that was cut off by a crash, so the new lines do not continue it. Only
+        // the last byte is read, unless the line was actually cut off.
+        if file.metadata()?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                let existing = fs::read(&self.path)?;
+                let complete = existing.iter().rposition(|&b| b == b'\n');
+                file.set_len(complete.map_or(0, |i| i + 1) as u64)?;
+            }
+        }
+
+        file.write_all(lines.as_bytes())?;
+        file.sync_all()?;
+        if let Some(count) = self.lines.get() {
+            self.lines.set(Some(count + entries.len()));
+        }
+        Ok(())
+    }
+
+    /// Replace the journal with a snapshot of `data`, moving the old events to the archive.
+    fn write_snapshot(&self, data: &Data) -> Result<(), Box<dyn Error>> {
+        if let Ok(old) = fs::read(&self.path) {
+            let mut archive = File::options()
+                .create(true)
+                .append(true)
+                .open(file_utils::with_suffix(&self.path, ".archive"))?;
+            archive.write_all(&old)?;
+            archive.sync_all()?;
+        }
+
+        let snapshot = Entry {
+            at: now(),
+            event: Event::Snapshot {
+                data: serde_json::to_value(data)?,
+            },
+        };
+        let line = format!("{}\n", serde_json::to_string(&snapshot)?);
+        file_utils::write_atomic(&self.path, line.as_bytes())?;
+        self.lines.set(Some(1));
+        Ok(())
+    }
+}
+
+impl Storage for JournalStorage {
+    fn read(&self) -> Result<(Data, u32), LoadError> {
+        let entries = self.read_entries()?;
+        if entries.is_empty() {
+            return Ok((Data::new(), migrations::CURRENT_VERSION));
+        }
+
+        let mut value = replay(entries);
+        let version = migrations::migrate(&mut value).map_err(LoadError::Incompatible)?;
+        let data = serde_json::from_value(value).map_err(LoadError::Corrupted)?;
+        Ok((data, version))
+    }
+
+    fn write(&self, previous: Option<&Data>, data: &Data) -> Result<(), Box<dyn Error>> {
+        // A new journal has to start with a snapshot, which the first events are applied to
+        let is_empty = fs::metadata(&self.path).map_or(true, |m| m.len() == 0);
+        let Some(previous) = previous.filter(|_| !is_empty) else {
+            return self.write_snapshot(data);
+        };
+
+        let entries: Vec<Entry> = diff(previous, data)?
+            .into_iter()
+            .map(|event| Entry { at: now(), event })
+            .collect();
+        self.append(&entries)?;
+
+        // The line count is known from loading the journal, so it does not have to be read again
+        if self.lines.get().is_some_and(|lines| lines > COMPACT_AFTER) {
+            self.write_snapshot(data)?;
+        }
+        Ok(())
+    }
+
+    fn compact(&self, data: &Data) -> Result<bool, Box<dyn Error>> {
+        self.write_snapshot(data)?;
+        Ok(true)
+    }
+}
+
+fn now() -> u64 {
+    time_utils::since_unix().as_millis() as u64
+}
+
+/// Describe the changes from `previous` to `data` as events.
+fn diff(previous: &Data, data: &Data) -> Result<Vec<Event>, serde_json::Error> {
+    let (Value::Object(mut old), Value::Object(mut new)) =
+        (serde_json::to_value(previous)?, serde_json::to_value(data)?)
+    else {
+        unreachable!("Data is always serialized as an object");
+    };
+    let old_blocks = blocks_by_id(old.remove("blocks"));
+    let new_blocks = blocks_by_id(new.remove("blocks"));
+    let mut events = vec![];
+
+    for (key, value) in new {
+        if old.get(&key) != Some(&value) {
+            events.push(Event::FieldChanged { key, value });
+        }
+    }
+    for old_block in previous
+        .blocks
+        .iter()
+        .filter(|b| !new_blocks.contains_key(&b.id))
+    {
+        events.push(Event::BlockDeleted { id: old_block.id });
+    }
+    for block in &data.blocks {
+        let value = new_blocks[&block.id].clone();
+        match old_blocks.get(&block.id) {
+            None => events.push(Event::BlockAdded { block: value }),
+            Some(old_value) if *old_value != value => {
+                events.push(Event::BlockEdited { block: value });
+            }
+            Some(_) => (),
+        }
+    }
+    Ok(events)
+}
+
+fn blocks_by_id(blocks: Option<Value>) -> HashMap<u64, Value> {
+    match blocks {
+        Some(Value::Array(blocks)) => blocks
+            .into_iter()
+            .map(|b| (b.get("id").and_then(Value::as_u64).unwrap_or(0), b))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Apply all events in order, starting from an empty data file.
+fn replay(entries: Vec<Entry>) -> Value {
+    let mut fields = Map::new();
+    let mut blocks: Vec<Value> = vec![];
+    let id_of = |b: &Value| b.get("id").and_then(Value::as_u64);
+
+    for entry in entries {
+        match entry.event {
+            Event::Snapshot { data } => {
+                if let Value::Object(mut data) = data {
+                    blocks = match data.remove("blocks") {
+                        Some(Value::Array(b)) => b,
+                        _ => vec![],
+                    };
+                    fields = data;
+                }
+            }
+            // Journals merged from two machines may add the same block twice
+            Event::BlockAdded { block } | Event::BlockEdited { block } => {
+                match blocks.iter().position(|b| id_of(b) == id_of(&block)) {
+                    Some(pos) => blocks[pos] = block,
+                    None => blocks.push(block),
+                }
+            }
+            Event::BlockDeleted { id } => blocks.retain(|b| id_of(b) != Some(id)),
+            Event::FieldChanged { key, value } => {
+                fields.insert(key, value);
+            }
+        }
+    }
+
+    fields.insert("blocks".to_string(), Value::Array(blocks));
+    Value::Object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::TimeBlock;
+
+    fn block(id: u64, category: &str) -> TimeBlock {
+        TimeBlock {
+            id,
+            start_unix: id * 1000,
+            end_unix: Some(id * 1000 + 500),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    #[test]
+    fn test_journal_replays_changes() {
+        let path = std::env::temp_dir().join(format!("zyr-{}.journal", std::process::id()));
+        let storage = JournalStorage::new(path.clone());
+
+        let mut first = Data::new();
+        first.blocks = vec![block(1, "code"), block(2, "study")];
+        storage.write(None, &first).unwrap();
+
+        let mut second = first.clone();
+        second.blocks.remove(0);
+        second.blocks[0].category = "review".to_string();
+        second.blocks.push(block(3, "break"));
+        second.settings.backup_days = 3;
+        storage.write(Some(&first), &second).unwrap();
+
+        // A line cut off by a crash is ignored
+        let mut file = File::options().append(true).open(&path).unwrap();
+        file.write_all(br#"{"at":1,"event":"block_del"#).unwrap();
+
+        let (read, _) = storage.read().unwrap();
+        assert_eq!(read, second);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 6);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_diff_detects_each_kind_of_change() {
+        let mut previous = Data::new();
+        previous.blocks = vec![block(1, "code"), block(2, "study")];
+        let mut data = previous.clone();
+        data.blocks[0].category = "review".to_string();
+        data.blocks.remove(1);
+        data.blocks.push(block(3, "break"));
+
+        let events = diff(&previous, &data).unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(events.contains(&Event::BlockDeleted { id: 2 }));
+        assert!(matches!(events[1], Event::BlockEdited { .. }));
+        assert!(matches!(events[2], Event::BlockAdded { .. }));
+    }
+
+    #[test]
+    fn test_journal_counts_lines_for_compaction() {
+        let path = std::env::temp_dir().join(format!("zyr-count-{}.journal", std::process::id()));
+        let storage = JournalStorage::new(path.clone());
+        let mut previous = Data::new();
+        storage.write(None, &previous).unwrap();
+
+        // A line cut off by a crash is dropped before appending
+        let mut file = File::options().append(true).open(&path).unwrap();
+        file.write_all(br#"{"at":1,"event":"block_del"#).unwrap();
+        let storage = JournalStorage::new(path.clone());
+        storage.read().unwrap();
+
+        for id in 1..=COMPACT_AFTER as u64 {
+            let mut data = previous.clone();
+            data.blocks.push(block(id, "code"));
+            storage.write(Some(&previous), &data).unwrap();
+            previous = data;
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+        assert_eq!(storage.read().unwrap().0, previous);
+        let archive = file_utils::with_suffix(&path, ".archive");
+        assert_eq!(
+            fs::read_to_string(&archive).unwrap().lines().count(),
+            COMPACT_AFTER + 1
+        );
+        fs::remove_file(path).unwrap();
+        fs::remove_file(archive).unwrap();
+    }
+}
@@ -0,0 +1,30 @@
+use super::{Storage, recovery};
+use crate::domain::{Data, LoadError};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Stores all data in a single JSON file, which is rewritten atomically on every change.
+pub struct JsonStorage {
+    path: PathBuf,
+}
+
+impl JsonStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn read(&self) -> Result<(Data, u32), LoadError> {
+        Data::read_file(&self.path)
+    }
+
+    fn write(&self, _previous: Option<&Data>, data: &Data) -> Result<(), Box<dyn Error>> {
+        data.save(&self.path)?;
+        Ok(())
+    }
+
+    fn recover(&self, error: LoadError) -> Result<Data, Box<dyn Error>> {
+        recovery::recover(&self.path, error)
+    }
+}
@@ -0,0 +1,143 @@
+use serde_json::{Map, Value};
+
+/// The version of the data file layout written by this build of `zyr`.
+///
+/// Bump this whenever a field of `Data` or one of the types it contains changes, and add the
+/// migration from the previous version to `MIGRATIONS`. This includes new fields with a
+/// `#[serde(default)]`: older builds would drop them when saving, so they have to refuse the file.
+pub const CURRENT_VERSION: u32 = 12;
+
+/// The key under which the schema version is stored in the data file.
+pub const VERSION_KEY: &str = "schema_version";
+
+/// A function that upgrades a data file by exactly one schema version.
+type Migration = fn(&mut Map<String, Value>);
+
+/// All migrations, where the element at index `i` upgrades a data file from version `i` to
+/// version `i + 1`.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [
+    v0_add_schema_version,
+    v1_add_settings,
+    v2_add_block_ids,
+    v3_add_trash,
+    v4_add_block_notes,
+    v5_add_work_hours,
+    v6_add_block_tags,
+    v7_add_recurrences,
+    v8_add_planned_blocks,
+    v9_add_templates,
+    v10_add_timer_queue,
+    v11_add_timer_presets,
+];
+
+/// Version 0 files predate the schema version and otherwise have the same layout as version 1.
+fn v0_add_schema_version(_: &mut Map<String, Value>) {}
+
+/// Version 1 files have no settings, which take their default values.
+fn v1_add_settings(_: &mut Map<String, Value>) {}
+
+/// Version 2 blocks have no ids. They are numbered from 1 in the order they were stored.
+fn v2_add_block_ids(data: &mut Map<String, Value>) {
+    if let Some(Value::Array(blocks)) = data.get_mut("blocks") {
+        for (i, block) in blocks.iter_mut().enumerate() {
+            if let Value::Object(block) = block {
+                block.insert("id".to_string(), Value::from(i + 1));
+            }
+        }
+    }
+}
+
+/// Version 3 files have no trash, which starts out empty.
+fn v3_add_trash(_: &mut Map<String, Value>) {}
+
+/// Version 4 blocks have no notes.
+fn v4_add_block_notes(_: &mut Map<String, Value>) {}
+
+/// Version 5 settings have no working hours, which take their default values.
+fn v5_add_work_hours(_: &mut Map<String, Value>) {}
+
+/// Version 6 blocks have no tags.
+fn v6_add_block_tags(_: &mut Map<String, Value>) {}
+
+/// Version 7 files have no recurring time blocks.
+fn v7_add_recurrences(_: &mut Map<String, Value>) {}
+
+/// Version 8 blocks are all tracked, none of them are planned.
+fn v8_add_planned_blocks(_: &mut Map<String, Value>) {}
+
+/// Version 9 files have no day templates.
+fn v9_add_templates(_: &mut Map<String, Value>) {}
+
+/// Version 10 files have no queued timers.
+fn v10_add_timer_queue(_: &mut Map<String, Value>) {}
+
+/// Version 11 files have no timer presets.
+fn v11_add_timer_presets(_: &mut Map<String, Value>) {}
+
+/// Read the schema version of a parsed data file. Files without one are version 0.
+pub fn version_of(value: &Value) -> u32 {
+    value
+        .get(VERSION_KEY)
+        .and_then(Value::as_u64)
+        .map_or(0, |v| v as u32)
+}
+
+/// Upgrade a parsed data file to `CURRENT_VERSION`.
+///
+/// * `value` - The contents of the data file, modified in place
+///
+/// * return - The version the file was upgraded from, or an error if the file was written by a
+///   newer version of `zyr` or is not a JSON object
+pub fn migrate(value: &mut Value) -> Result<u32, String> {
+    let from = version_of(value);
+    if from > CURRENT_VERSION {
+        return Err(format!(
+            "The data file has schema version {from}, but this version of zyr only supports up to version {CURRENT_VERSION}. Please update zyr."
+        ));
+    }
+
+    let object = value
+        .as_object_mut()
+        .ok_or("The data file does not contain a JSON object")?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        migration(object);
+        object.insert(VERSION_KEY.to_string(), Value::from(version + 1));
+    }
+    Ok(from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate_unversioned_file() {
+        let mut value = json!({"categories": ["break"], "blocks": []});
+        assert_eq!(migrate(&mut value), Ok(0));
+        assert_eq!(version_of(&value), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_migrate_numbers_blocks() {
+        let mut value =
+            json!({"schema_version": 2, "blocks": [{"start_unix": 5}, {"start_unix": 1}]});
+        migrate(&mut value).unwrap();
+        assert_eq!(value["blocks"][0]["id"], 1);
+        assert_eq!(value["blocks"][1]["id"], 2);
+    }
+
+    #[test]
+    fn test_migrate_current_file_unchanged() {
+        let mut value = json!({"schema_version": CURRENT_VERSION, "blocks": []});
+        let before = value.clone();
+        assert_eq!(migrate(&mut value), Ok(CURRENT_VERSION));
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn test_migrate_newer_file() {
+        let mut value = json!({"schema_version": CURRENT_VERSION + 1, "blocks": []});
+        assert!(migrate(&mut value).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use super::backups;
+use crate::domain::{Data, LoadError, TimeBlock};
+use crate::utils::{file_utils, io_utils, time_utils};
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Get the user's data back after the data file could not be loaded.
+///
+/// Offers to restore the latest backup or to keep the time blocks that can still be read. Nothing
+/// is written without the user's consent, and the broken file is kept with a `.corrupt` suffix.
+/// A missing data file is created empty if the user declines, so `zyr` stays usable.
+///
+/// * `path`  - The path of the data file
+/// * `error` - The reason why the data file could not be loaded
+///
+/// * return - The recovered data, or an error if the user did not agree to any recovery
+pub fn recover(path: &Path, error: LoadError) -> Result<Data, Box<dyn Error>> {
+    recover_with(path, error, io_utils::confirm)
+}
+
+/// Like `recover`, asking the user with `confirm` before anything is written.
+fn recover_with(
+    path: &Path,
+    error: LoadError,
+    mut confirm: impl FnMut(&str) -> bool,
+) -> Result<Data, Box<dyn Error>> {
+    let backup = latest_backup(path);
+
+    match (&error, &backup) {
+        (LoadError::Unreadable(_) | LoadError::Incompatible(_), _) => return Err(error.into()),
+        (LoadError::Missing, None) => {
+            // First run with this data file
+            file_utils::create_if_missing(path)
+                .map_err(|e| format!("Could not create {}: {e}", path.display()))?;
+            return Ok(Data::from_file(path)?);
+        }
+        _ => (),
+    }
+
+    eprintln!("{error} ({})", path.display());
+
+    if let Some((data, backup_path)) = backup {
+        eprintln!(
+            "A backup with {} time blocks was found at {}.",
+            data.blocks.len(),
+            backup_path.display()
+        );
+        if confirm("restore the data from this backup") {
+            replace(path, &data)?;
+            return Ok(data);
+        }
+    }
+
+    if let LoadError::Corrupted(_) = error
+        && let Ok(text) = fs::read_to_string(path)
+        && let Some(data) = salvage(&text)
+    {
+        eprintln!(
+            "{} time blocks could still be read from the corrupted file.",
+            data.blocks.len()
+        );
+        if confirm("keep only the time blocks that could be read") {
+            replace(path, &data)?;
+            return Ok(data);
+        }
+    }
+
+    if let LoadError::Missing = error {
+        // The backups are kept, so they can still be restored with `zyr backup restore`
+        eprintln!("Starting with an empty data file instead.");
+        file_utils::create_if_missing(path)
+            .map_err(|e| format!("Could not create {}: {e}", path.display()))?;
+        return Ok(Data::from_file(path)?);
+    }
+
+    Err(format!(
+        "The data file was left untouched. Fix or remove {} manually to continue.",
+        path.display()
+    )
+    .into())
+}
+
+/// Find the most recent backup of a data file that can still be loaded, trying the `.bak` file
+/// before the snapshots in the backups directory.
+fn latest_backup(path: &Path) -> Option<(Data, PathBuf)> {
+    let backup_path = file_utils::get_backup_path(path);
+    if let Ok(data) = Data::from_file(&backup_path) {
+        return Some((data, backup_path));
+    }
+    backups::list(path)
+        .into_iter()
+        .rev()
+        .find_map(|s| Some((s.load().ok()?, s.path)))
+}
+
+/// Set the broken data file aside and write `data` in its place.
+fn replace(path: &Path, data: &Data) -> io::Result<()> {
+    if path.exists() {
+        let secs = time_utils::since_unix().as_secs();
+        let corrupt_path = file_utils::with_suffix(path, &format!(".corrupt-{secs}"));
+        fs::rename(path, &corrupt_path)?;
+        eprintln!(
+            "The broken data file was moved to {}",
+            corrupt_path.display()
+        );
+    }
+    data.save(path)
+}
+
+/// Read as many time blocks as possible from a data file that is not valid JSON anymore.
+///
+/// * return - `Data` holding the blocks that could be parsed, or `None` if none could be
+pub fn salvage(text: &str) -> Option<Data> {
+    let blocks: Vec<TimeBlock> = salvage_array(text, "blocks");
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let mut data = Data::new();
+    data.blocks = blocks;
+    data.fix_block_ids();
+    Some(data)
+}
+
+/// Parse the elements of the JSON array stored under `key`, skipping the ones that are broken
+/// and stopping at the end of the array or of the text.
+fn salvage_array<T: DeserializeOwned>(text: &str, key: &str) -> Vec<T> {
+    let mut items = vec![];
+    let Some(key_pos) = text.find(&format!("\"{key}\"")) else {
+        return items;
+    };
+    let Some(mut rest) = text[key_pos + key.len() + 2..]
+        .trim_start()
+        .strip_prefix(':')
+        .and_then(|r| r.trim_start().strip_prefix('['))
+    else {
+        return items;
+    };
+
+    loop {
+        rest = rest.trim_start();
+        let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<T>();
+        match stream.next() {
+            Some(Ok(item)) => {
+                items.push(item);
+                rest = rest[stream.byte_offset()..].trim_start();
+                match rest.strip_prefix(',') {
+                    Some(r) => rest = r,
+                    None => break,
+                }
+            }
+            _ => {
+                // Skip the broken element, unless the array ends before the next one starts
+                let next = rest.get(1..).and_then(|r| r.find('{')).map(|i| i + 1);
+                let end = rest.find(']');
+                match (next, end) {
+                    (Some(n), Some(e)) if e < n => break,
+                    (Some(n), _) => rest = &rest[n..],
+                    (None, _) => break,
+                }
+            }
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_salvage_truncated_file() {
+        let text = r#"{"categories":["break"],"blocks":[{"start_unix":1,"end_unix":2,"category":"code"},{"start_unix":3,"end_unix":4,"category":"study"},{"start_unix":5,"end_"#;
+        let data = salvage(text).unwrap();
+        assert_eq!(data.blocks.len(), 2);
+        assert_eq!(data.blocks[1].category, "study");
+    }
+
+    #[test]
+    fn test_salvage_skips_broken_block() {
+        let text = r#"{"blocks":[{"start_unix":1,"end_unix":2,"category":"code"},{"start_unix":x,"end_unix":4,"category":"study"},{"start_unix":5,"end_unix":null,"category":"break"}]}"#;
+        let data = salvage(text).unwrap();
+        let categories: Vec<&str> = data.blocks.iter().map(|b| &b.category[..]).collect();
+        assert_eq!(categories, vec!["code", "break"]);
+    }
+
+    #[test]
+    fn test_salvage_nothing_readable() {
+        assert!(salvage("\0\0\0\0").is_none());
+    }
+
+    #[test]
+    fn test_recover_missing_file_with_declined_backup() {
+        let dir = std::env::temp_dir().join(format!("zyr-recover-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        let mut backup = Data::new();
+        backup.blocks = salvage(r#"{"blocks":[{"start_unix":1,"end_unix":2,"category":"code"}]}"#)
+            .unwrap()
+            .blocks;
+        backup.save(&file_utils::get_backup_path(&path)).unwrap();
+
+        let data = recover_with(&path, LoadError::Missing, |_| false).unwrap();
+        assert!(data.blocks.is_empty());
+        assert!(path.exists());
+        assert!(Data::from_file(&path).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+use super::{Storage, migrations};
+use crate::domain::{Data, LoadError, TimeBlock};
+use rusqlite::{Connection, Transaction, params};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The columns of the `blocks` table. All other fields of a block are kept as a JSON object in
+/// the `extra` column, so new fields do not require changes to the tables.
+const BLOCK_COLUMNS: [&str; 4] = ["id", "start_unix", "end_unix", "category"];
+
+/// Creates the tables if they do not exist yet.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS blocks (
+        id INTEGER PRIMARY KEY,
+        start_unix INTEGER NOT NULL,
+        end_unix INTEGER,
+        category TEXT NOT NULL,
+        extra TEXT
+    );
+    CREATE INDEX IF NOT EXISTS blocks_start_unix ON blocks (start_unix);
+    CREATE INDEX IF NOT EXISTS blocks_category ON blocks (category);
+";
+
+/// Stores time blocks as rows of an SQLite database and every other field of `Data` as a JSON
+/// value in the `meta` table. Only the blocks that changed are written.
+pub struct SqliteStorage {
+    path: PathBuf,
+}
+
+impl SqliteStorage {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Open the database, creating it and its tables if needed.
+    fn connect(&self) -> Result<Connection, Box<dyn Error>> {
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(&self.path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+
+    /// Read the database into the JSON layout of a data file.
+    ///
+    /// * return - The data file contents, or `None` if the database is empty
+    fn read_value(&self) -> Result<Option<Value>, Box<dyn Error>> {
+        let conn = self.connect()?;
+        let mut object = Map::new();
+
+        let mut stmt = conn.prepare("SELECT key, value FROM meta")?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (key, value) = row?;
+            object.insert(key, serde_json::from_str(&value)?);
+        }
+        if object.is_empty() {
+            return Ok(None);
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT id, start_unix, end_unix, category, extra FROM blocks ORDER BY start_unix, id",
+        )?;
+        let rows = stmt.query_map([], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, Option<i64>>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        let mut blocks = vec![];
+        for row in rows {
+            let (id, start_unix, end_unix, category, extra) = row?;
+            let mut block: Map<String, Value> = match extra {
+                Some(e) => serde_json::from_str(&e)?,
+                None => Map::new(),
+            };
+            block.insert("id".to_string(), Value::from(id));
+            block.insert("start_unix".to_string(), Value::from(start_unix));
+            block.insert("end_unix".to_string(), Value::from(end_unix));
+            block.insert("category".to_string(), Value::from(category));
+            blocks.push(Value::Object(block));
+        }
+        object.insert("blocks".to_string(), Value::Array(blocks));
+
+        Ok(Some(Value::Object(object)))
+    }
+
+    /// Insert a block, or replace the row with the same id.
+    fn upsert_block(tx: &Transaction, block: &TimeBlock) -> Result<(), Box<dyn Error>> {
+        let Value::Object(mut extra) = serde_json::to_value(block)? else {
+            unreachable!("Blocks are always serialized as objects");
+        };
+        for column in BLOCK_COLUMNS {
+            extra.remove(column);
+        }
+        let extra = (!extra.is_empty()).then(|| Value::Object(extra).to_string());
+
+        tx.execute(
+            "INSERT OR REPLACE INTO blocks (id, start_unix, end_unix, category, extra) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                block.id as i64,
+                block.start_unix as i64,
+                block.end_unix.map(|e| e as i64),
+                block.category,
+                extra
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn read(&self) -> Result<(Data, u32), LoadError> {
+        let value = self
+            .read_value()
+            .map_err(|e| LoadError::Unreadable(io::Error::other(e.to_string())))?;
+        let Some(mut value) = value else {
+            return Ok((Data::new(), migrations::CURRENT_VERSION));
+        };
+
+        let version = migrations::migrate(&mut value).map_err(LoadError::Incompatible)?;
+        let data = serde_json::from_value(value).map_err(LoadError::Corrupted)?;
+        Ok((data, version))
+    }
+
+    fn write(&self, previous: Option<&Data>, data: &Data) -> Result<(), Box<dyn Error>> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction()?;
+
+        let Value::Object(mut meta) = serde_json::to_value(data)? else {
+            unreachable!("Data is always serialized as an object");
+        };
+        meta.remove("blocks");
+        for (key, value) in meta {
+            tx.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                params![key, value.to_string()],
+            )?;
+        }
+
+        match previous {
+            None => {
+                tx.execute("DELETE FROM blocks", [])?;
+                for block in &data.blocks {
+                    Self::upsert_block(&tx, block)?;
+                }
+            }
+            Some(previous) => {
+                let ids: HashSet<u64> = data.blocks.iter().map(|b| b.id).collect();
+                for block in previous.blocks.iter().filter(|b| !ids.contains(&b.id)) {
+                    tx.execute("DELETE FROM blocks WHERE id = ?1", [block.id as i64])?;
+                }
+
+                let previous: HashMap<u64, &TimeBlock> =
+                    previous.blocks.iter().map(|b| (b.id, b)).collect();
+                for block in &data.blocks {
+                    if previous.get(&block.id) != Some(&block) {
+                        Self::upsert_block(&tx, block)?;
+                    }
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(id: u64, category: &str) -> TimeBlock {
+        TimeBlock {
+            id,
+            start_unix: id * 1000,
+            end_unix: Some(id * 1000 + 500),
+            category: category.to_string(),
+            note: None,
+            tags: vec![],
+            planned: false,
+        }
+    }
+
+    #[test]
+    fn test_sqlite_write_changes_round_trip() {
+        let path = std::env::temp_dir().join(format!("zyr-sqlite-{}.sqlite", std::process::id()));
+        let storage = SqliteStorage::new(path.clone());
+
+        let mut first = Data::new();
+        first.blocks = vec![block(1, "code"), block(2, "study"), block(3, "break")];
+        storage.write(None, &first).unwrap();
+
+        let mut second = first.clone();
+        second.blocks.remove(1);
+        second.blocks[0].category = "review".to_string();
+        second.blocks.push(block(4, "code"));
+        storage.write(Some(&first), &second).unwrap();
+
+        let (read, version) = storage.read().unwrap();
+        assert_eq!(version, migrations::CURRENT_VERSION);
+        assert_eq!(read, second);
+        fs::remove_file(path).unwrap();
+    }
+}
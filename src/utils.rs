@@ -1,7 +1,7 @@
 /// Custom parsers for command line arguments
 pub mod parsers {
 
-    use chrono::{DateTime, Local, TimeZone, Utc};
+    use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
     use std::time::Duration;
 
     /// Parse a duration string like "1h35m50s" into `Duration`
@@ -32,6 +32,32 @@ pub mod parsers {
         Ok(Duration::from_secs(secs))
     }
 
+    /// Parse a signed duration like "+15m" or "-1h30m" into a number of milliseconds
+    pub fn parse_offset(s: &str) -> Result<i64, String> {
+        let s = s.trim();
+        let (sign, magnitude) = match s.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if magnitude.is_empty() {
+            return Err(format!("Invalid offset {s}, expected e.g. +15m or -1h"));
+        }
+        Ok(sign * parse_duration(magnitude)?.as_millis() as i64)
+    }
+
+    /// Parse a step of a timer queue like "code:50m" into its category and duration
+    pub fn parse_queue_step(s: &str) -> Result<(String, Duration), String> {
+        let (category, duration) = s
+            .rsplit_once(':')
+            .filter(|(c, _)| !c.is_empty())
+            .ok_or_else(|| format!("Invalid timer {s}, expected e.g. code:50m"))?;
+        let duration = parse_duration(duration)?;
+        if duration.is_zero() {
+            return Err(format!("The timer {s} has no duration"));
+        }
+        Ok((category.to_string(), duration))
+    }
+
     /// Parse an RFC 3339 timestamp like "2010-12-31T20:00:00" into `DateTime<Local>`
     pub fn parse_timestamp(s: &str) -> Result<DateTime<Local>, String> {
         let humantime_result = humantime::parse_rfc3339_weak(s);
@@ -44,44 +70,309 @@ pub mod parsers {
             Err(e) => Err(e.to_string()),
         }
     }
+
+    /// Parse a date like "2010-12-31", "today", "yesterday", "tomorrow" or a weekday like
+    /// "monday" into `NaiveDate`. Weekdays refer to the current week, which starts on Monday.
+    pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+        let today = Local::now().date_naive();
+        match s.trim().to_lowercase().as_str() {
+            "today" => Ok(today),
+            "yesterday" => Ok(today - Days::new(1)),
+            "tomorrow" => Ok(today + Days::new(1)),
+            other => {
+                if let Ok(weekday) = other.parse::<Weekday>() {
+                    return Ok(today.week(Weekday::Mon).first_day()
+                        + Days::new(weekday.num_days_from_monday().into()));
+                }
+                NaiveDate::parse_from_str(other, "%Y-%m-%d").map_err(|_| {
+                    format!("Invalid date {s}, expected e.g. 2010-12-31, today or monday")
+                })
+            }
+        }
+    }
+
+    /// Parse a time of day like "10:30" or "10:30:15" into `NaiveTime`
+    pub fn parse_time(s: &str) -> Result<NaiveTime, String> {
+        NaiveTime::parse_from_str(s, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+            .map_err(|_| format!("Invalid time {s}, expected e.g. 10:30"))
+    }
+
+    /// Parse a set of weekdays like "daily", "weekdays", "weekends" or "mon,wed,fri"
+    ///
+    /// * return - The days, counted from 0 for Monday to 6 for Sunday
+    pub fn parse_weekdays(s: &str) -> Result<Vec<u32>, String> {
+        let mut days: Vec<u32> = match s.trim().to_lowercase().as_str() {
+            "daily" => (0..7).collect(),
+            "weekdays" => (0..5).collect(),
+            "weekends" => vec![5, 6],
+            list => list
+                .split(',')
+                .map(|day| {
+                    day.trim()
+                        .parse::<Weekday>()
+                        .map(|d| d.num_days_from_monday())
+                        .map_err(|_| {
+                            format!("Invalid days {s}, expected e.g. daily, weekdays or mon,wed")
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        days.sort();
+        days.dedup();
+        Ok(days)
+    }
+
+    /// Parse a timestamp like "2010-12-31T10:00:00", or a time of day like "10:00" on `day`
+    pub fn parse_moment(s: &str, day: NaiveDate) -> Result<DateTime<Local>, String> {
+        if let Ok(dt) = parse_timestamp(s) {
+            return Ok(dt);
+        }
+        let time = parse_time(s)
+            .map_err(|_| format!("Invalid time {s}, expected e.g. 10:30 or 2010-12-31T10:30:00"))?;
+        Local
+            .from_local_datetime(&day.and_time(time))
+            .earliest()
+            .ok_or_else(|| {
+                format!("{s} does not exist on {day} because of a daylight saving time change")
+            })
+    }
+
+    /// Parse a range of dates like "2010-12-01..2010-12-31" into its first and last day
+    pub fn parse_date_range(s: &str) -> Result<(NaiveDate, NaiveDate), String> {
+        let (from, to) = s
+            .split_once("..")
+            .ok_or_else(|| format!("Invalid range {s}, expected e.g. 2010-12-01..2010-12-31"))?;
+        let (from, to) = (parse_date(from)?, parse_date(to)?);
+        if from > to {
+            return Err(format!("The range {s} ends before it starts"));
+        }
+        Ok((from, to))
+    }
 }
 
 /// Various file system utility functions
 pub mod file_utils {
 
+    use std::env;
+    use std::error::Error;
+    use std::ffi::OsString;
     use std::fs::{self, File};
-    use std::path::PathBuf;
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    use std::process;
 
     use crate::domain::Data;
 
-    /// Return a pointer to the `PathBuf` where the user data is stored.
-    ///
-    /// Create the file and writes a base `Data` JSON object to it if it does not exist.
+    /// The name of the profile whose data lives in the original `data.json` file.
+    pub const DEFAULT_PROFILE: &str = "default";
+
+    /// The environment variable that overrides the location of the data file.
+    pub const DATA_ENV_VAR: &str = "ZYR_DATA";
+
+    /// Return the directory where `zyr` stores all of its files.
     ///
     /// # Example paths
     ///
-    /// Lin: /home/john/.local/share/zyr/data.json
-    /// Win: C:\Users\John\AppData\Roaming\zyr\zyr\data\data.json
-    /// Mac: /Users/John/Library/Application Support/org/zyr/zyr/data.json
-    pub fn get_data_path() -> PathBuf {
-        let file_path = directories::ProjectDirs::from("org", "zyr", "zyr")
+    /// Lin: /home/john/.local/share/zyr
+    /// Win: C:\Users\John\AppData\Roaming\zyr\zyr\data
+    /// Mac: /Users/John/Library/Application Support/org/zyr/zyr
+    pub fn get_project_dir() -> PathBuf {
+        directories::ProjectDirs::from("org", "zyr", "zyr")
             .expect("Could not open the project directory")
             .data_dir()
-            .join("data.json");
+            .to_path_buf()
+    }
+
+    /// Return the path of the data file belonging to a profile.
+    ///
+    /// The default profile uses `data.json` in the project directory, every other profile uses
+    /// `profiles/{name}.json`. Profiles moved to another storage format with
+    /// `zyr storage migrate` use the extension of that format instead.
+    pub fn get_profile_path(name: &str) -> PathBuf {
+        let path = if name == DEFAULT_PROFILE {
+            get_project_dir().join("data")
+        } else {
+            get_project_dir().join("profiles").join(name)
+        };
+
+        ["sqlite", "journal"]
+            .into_iter()
+            .map(|extension| path.with_extension(extension))
+            .find(|p| p.exists())
+            .unwrap_or_else(|| path.with_extension("json"))
+    }
+
+    /// Return the name of the profile selected with `zyr profile switch`.
+    pub fn get_active_profile() -> String {
+        fs::read_to_string(get_project_dir().join("profile"))
+            .map(|s| s.trim().to_string())
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+    }
+
+    /// Persist the profile that is used when neither `--data`, `--profile` nor `ZYR_DATA` is set.
+    pub fn set_active_profile(name: &str) -> Result<(), Box<dyn Error>> {
+        let dir = get_project_dir();
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("profile"), name)?;
+        Ok(())
+    }
+
+    /// Return the names of all existing profiles, sorted alphabetically.
+    ///
+    /// The default profile is always part of the list.
+    pub fn list_profiles() -> Vec<String> {
+        list_profiles_in(&get_project_dir().join("profiles"))
+    }
+
+    /// Return the names of the profiles whose data files are in `dir`, plus the default profile.
+    ///
+    /// Files kept next to a data file, like `work.history.json`, are not profiles. Their names
+    /// contain a dot, which profile names cannot.
+    pub fn list_profiles_in(dir: &Path) -> Vec<String> {
+        let mut names = vec![DEFAULT_PROFILE.to_string()];
+        if let Ok(entries) = fs::read_dir(dir) {
+            names.extend(entries.filter_map(|e| {
+                let path = e.ok()?.path();
+                if !["json", "sqlite", "journal"].contains(&path.extension()?.to_str()?) {
+                    return None;
+                }
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                validate_profile_name(&name).is_ok().then_some(name)
+            }));
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Check that a profile name can safely be used as a file name.
+    pub fn validate_profile_name(name: &str) -> Result<(), String> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Invalid profile name \"{name}\". Only letters, digits, '-' and '_' are allowed."
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decide which data file this invocation of `zyr` works with.
+    ///
+    /// In order of precedence: the `--data` flag, the `--profile` flag, the `ZYR_DATA`
+    /// environment variable and finally the active profile.
+    ///
+    /// * `data`    - The path passed with `--data`
+    /// * `profile` - The profile passed with `--profile`
+    ///
+    /// * return - The path of the data file, or an error
+    pub fn resolve_data_path(
+        data: Option<&Path>,
+        profile: Option<&str>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let file_path = if let Some(path) = data {
+            path.to_path_buf()
+        } else if let Some(name) = profile {
+            validate_profile_name(name)?;
+            let path = get_profile_path(name);
+            if name != DEFAULT_PROFILE && !path.exists() {
+                return Err(format!(
+                    "Profile \"{name}\" does not exist. Create it with `zyr profile create {name}`"
+                )
+                .into());
+            }
+            path
+        } else if let Some(path) = env::var_os(DATA_ENV_VAR).filter(|p| !p.is_empty()) {
+            PathBuf::from(path)
+        } else {
+            get_profile_path(&get_active_profile())
+        };
+
+        Ok(file_path)
+    }
+
+    /// Return `path` with `suffix` appended to its file name, e.g. `data.json` -> `data.json.bak`.
+    pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+        let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+        name.push(suffix);
+        path.with_file_name(name)
+    }
+
+    /// Return the path where the previous version of a data file is kept.
+    pub fn get_backup_path(path: &Path) -> PathBuf {
+        with_suffix(path, ".bak")
+    }
+
+    /// Replace the contents of `path` in a way that survives crashes and full disks.
+    ///
+    /// The contents are written to a temporary file in the same directory and flushed to disk,
+    /// after which the temporary file is renamed over the original. The original file is never
+    /// truncated, so it either holds the old or the new contents. Before the rename, the old
+    /// contents are copied to the `.bak` file.
+    pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+        let tmp_path = with_suffix(path, &format!(".{}.tmp", process::id()));
+
+        let result = (|| {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(contents)?;
+            tmp.sync_all()?;
+            drop(tmp);
+
+            if fs::metadata(path).is_ok_and(|m| m.len() > 0) {
+                fs::copy(path, get_backup_path(path))?;
+            }
+            fs::rename(&tmp_path, path)
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+            return result;
+        }
+
+        // Make the rename itself durable. Directories cannot be opened like this on Windows.
+        #[cfg(unix)]
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Take an exclusive advisory lock on a data file, waiting for other `zyr` processes to
+    /// release it first.
+    ///
+    /// The lock is held on a separate `.lock` file, because the data file itself is replaced on
+    /// every save. It is released when the returned file is dropped.
+    pub fn lock(path: &Path) -> io::Result<File> {
+        let lock_file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(with_suffix(path, ".lock"))?;
+        lock_file.lock()?;
+        Ok(lock_file)
+    }
 
+    /// Create the file and write a base `Data` JSON object to it if it does not exist.
+    ///
+    /// * return - Nothing, or an error if the file or its directory cannot be created
+    pub fn create_if_missing(file_path: &Path) -> io::Result<()> {
         match file_path.try_exists() {
             Err(_) | Ok(false) => {
-                if let Some(parent) = file_path.parent() {
-                    fs::create_dir_all(parent).expect("Could not create project directory");
+                if let Some(parent) = file_path.parent()
+                    && !parent.as_os_str().is_empty()
+                {
+                    fs::create_dir_all(parent)?;
                 }
 
-                let _ = File::create(&file_path);
-                Data::new().save(&file_path);
+                Data::new().save(file_path)
             }
-            _ => (),
+            _ => Ok(()),
         }
-
-        file_path
     }
 }
 
@@ -104,12 +395,28 @@ pub mod io_utils {
 
         buf.chars().next().is_some_and(|ch| ch == 'y')
     }
+
+    /// Ask the user to type a line of text.
+    ///
+    /// * `msg` - The question shown to the user
+    ///
+    /// * return - The trimmed answer, or `None` if the input has ended
+    pub fn ask(msg: &str) -> Option<String> {
+        println!("{msg}");
+        let mut buf = String::new();
+        match io::stdin().read_line(&mut buf) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(buf.trim().to_string()),
+        }
+    }
 }
 
 /// Various utility functions for dealing with durations and timestamps
 pub mod time_utils {
 
-    use chrono::{DateTime, Datelike, Local, TimeZone, Timelike, Utc};
+    use chrono::{
+        DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone, Timelike, Utc,
+    };
     use std::fmt::Write;
     use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -156,6 +463,44 @@ pub mod time_utils {
         (dt.hour() * 3_600_000 + dt.minute() * 60_000 + dt.second() * 1_000) as u64
     }
 
+    /// Compute the start of a local date, which is midnight unless a daylight saving time
+    /// change skips it.
+    ///
+    /// * return - The number of milliseconds since the Unix epoch
+    pub fn start_of_day(date: NaiveDate) -> u64 {
+        at_time(date, NaiveTime::MIN)
+    }
+
+    /// Compute the moment a local date reaches a time of day. Times skipped by a daylight saving
+    /// time change are moved an hour later, and repeated times refer to their first occurrence.
+    ///
+    /// * return - The number of milliseconds since the Unix epoch
+    pub fn at_time(date: NaiveDate, time: NaiveTime) -> u64 {
+        let naive = date.and_time(time);
+        let moment = Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .or_else(|| {
+                Local
+                    .from_local_datetime(&(naive + TimeDelta::hours(1)))
+                    .earliest()
+            })
+            .unwrap_or_else(|| Local.from_utc_datetime(&naive));
+        moment.timestamp_millis() as u64
+    }
+
+    /// Move a moment by whole days while keeping its local time of day, even across daylight
+    /// saving time changes.
+    ///
+    /// * `millis` - The number of milliseconds since 1 Jan 1970
+    /// * `days`   - How many days to move, negative to move to earlier days
+    ///
+    /// * return - The number of milliseconds since the Unix epoch
+    pub fn shift_days(millis: u64, days: i64) -> u64 {
+        let moved = convert(millis).naive_local() + TimeDelta::days(days);
+        at_time(moved.date(), moved.time())
+    }
+
     /// Formats a `Duration` into a `String` like `14h35m20s`
     pub fn prettify_duration(d: Duration) -> String {
         let mut result = String::new();
@@ -249,4 +594,121 @@ mod tests {
         let result = parsers::parse_duration("73q");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_date_keywords() {
+        let today = chrono::Local::now().date_naive();
+        assert_eq!(parsers::parse_date("today"), Ok(today));
+        assert_eq!(
+            parsers::parse_date("Yesterday"),
+            Ok(today.pred_opt().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_offset_signs() {
+        assert_eq!(parsers::parse_offset("+15m"), Ok(15 * 60 * 1000));
+        assert_eq!(parsers::parse_offset("-1h"), Ok(-3600 * 1000));
+        assert_eq!(parsers::parse_offset("30s"), Ok(30 * 1000));
+        assert!(parsers::parse_offset("-").is_err());
+    }
+
+    #[test]
+    fn test_parse_date_weekdays() {
+        let monday = parsers::parse_date("monday").unwrap();
+        assert_eq!(chrono::Datelike::weekday(&monday), chrono::Weekday::Mon);
+        assert_eq!(
+            parsers::parse_date("Fri"),
+            Ok(monday + chrono::Days::new(4))
+        );
+        assert!(monday <= chrono::Local::now().date_naive());
+    }
+
+    #[test]
+    fn test_parse_weekdays_names_and_sets() {
+        assert_eq!(parsers::parse_weekdays("weekdays"), Ok(vec![0, 1, 2, 3, 4]));
+        assert_eq!(parsers::parse_weekdays("fri, Mon,mon"), Ok(vec![0, 4]));
+        assert!(parsers::parse_weekdays("mon,someday").is_err());
+    }
+
+    #[test]
+    fn test_parse_queue_step() {
+        assert_eq!(
+            parsers::parse_queue_step("code:50m"),
+            Ok(("code".to_string(), std::time::Duration::from_secs(50 * 60)))
+        );
+        assert!(parsers::parse_queue_step("code").is_err());
+        assert!(parsers::parse_queue_step(":5m").is_err());
+        assert!(parsers::parse_queue_step("code:0m").is_err());
+    }
+
+    #[test]
+    fn test_parse_date_range_reversed() {
+        assert!(parsers::parse_date_range("2024-01-01..2024-01-31").is_ok());
+        assert!(parsers::parse_date_range("2024-01-31..2024-01-01").is_err());
+    }
+
+    #[test]
+    fn test_shift_days_keeps_time_of_day() {
+        let day = chrono::NaiveDate::from_ymd_opt(2024, 3, 30).unwrap();
+        let ten = chrono::NaiveTime::from_hms_opt(10, 0, 0).unwrap();
+        let moved = time_utils::shift_days(time_utils::at_time(day, ten), 2);
+        assert_eq!(moved, time_utils::at_time(day + chrono::Days::new(2), ten));
+        assert_eq!(
+            time_utils::shift_days(moved, -2),
+            time_utils::at_time(day, ten)
+        );
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_previous_version() {
+        let dir = std::env::temp_dir().join(format!("zyr-atomic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+
+        file_utils::write_atomic(&path, b"first").unwrap();
+        file_utils::write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        let backup = file_utils::get_backup_path(&path);
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), "first");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_profiles_skips_sidecar_files() {
+        let dir = std::env::temp_dir().join(format!("zyr-profiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "work.json",
+            "work.history.json",
+            "work.json.bak",
+            "home.sqlite",
+            "home.history.json",
+        ] {
+            std::fs::write(dir.join(name), "{}").unwrap();
+        }
+
+        assert_eq!(
+            file_utils::list_profiles_in(&dir),
+            vec!["default", "home", "work"]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_profile_name_valid() {
+        assert!(file_utils::validate_profile_name("work-2024_q1").is_ok());
+    }
+
+    #[test]
+    fn test_validate_profile_name_path_separator() {
+        assert!(file_utils::validate_profile_name("../work").is_err());
+    }
+
+    #[test]
+    fn test_validate_profile_name_empty() {
+        assert!(file_utils::validate_profile_name("").is_err());
+    }
 }